@@ -1,9 +1,14 @@
-use crate::auth::AuthError;
+use crate::auth::{AuthError, AuthWorker, PamMessage, prompt_label};
+use crate::config::{Config, LabelConfig};
+use crate::desktop::{join_exec, split_exec};
 use crate::state::FerriteState;
-use crate::util::Session;
+use crate::util::{Session, fallback_session};
+use crate::widgets::widget::InputField;
 use crate::widgets::{select::SelectField, text::TextField};
 use tui_input::Input;
 
+const PASSWORD_MASK: &str = "*";
+
 #[derive(Debug)]
 pub enum AuthState {
     None,
@@ -19,7 +24,25 @@ pub struct AppState {
     pub focus_index: u8,
     pub max_focus_index: u8,
     pub auth_state: AuthState,
+    pub auth_worker: Option<AuthWorker>,
+    pub pam_message: Option<PamMessage>,
     pub hostname: String,
+    pub tick: usize,
+    /// Command line edited while the "Custom command…" session entry is selected.
+    pub command: TextField,
+    /// Overlay for editing the selected session's command line before launching.
+    pub editor: Option<TextField>,
+    /// Edited argv for the session entry at the given index, used for one login.
+    pub exec_override: Option<(usize, Vec<String>)>,
+    /// Session entry selected for users with nothing remembered: the configured
+    /// default, or the first entry.
+    pub default_session: usize,
+    pub state: FerriteState,
+    /// Problem loading or saving the state file, shown in the footer.
+    pub state_notice: Option<String>,
+    /// Tick at which to exit, set to keep a notice on screen after login.
+    pub quit_at: Option<usize>,
+    pub labels: LabelConfig,
 }
 
 impl AppState {
@@ -28,45 +51,181 @@ impl AppState {
         users: Vec<String>,
         hostname: String,
         state: FerriteState,
+        config: &Config,
     ) -> Self {
-        let last_session = state
-            .last_session
-            .as_deref()
-            .and_then(|name| sessions.iter().position(|s| s.name == name));
+        let labels = config.labels.clone();
+        let no_sessions = sessions.is_empty();
+        let mut sessions = if no_sessions {
+            vec![fallback_session(config)]
+        } else {
+            sessions
+        };
+        sessions.extend(
+            state
+                .recent_commands
+                .iter()
+                .filter_map(|line| Session::custom(line)),
+        );
+        sessions.push(Session::custom_entry(&labels.custom_entry));
 
-        let last_user = state
-            .last_user
+        let last_user = config
+            .defaults
+            .user
             .as_deref()
+            .or(state.last_user.as_deref())
             .and_then(|user| users.iter().position(|u| u == user));
 
-        let focus_index = (last_session.is_some() && last_user.is_some()) as u8 * 2;
+        let preselected = config.defaults.session.as_ref().and_then(|wanted| {
+            sessions
+                .iter()
+                .position(|s| &s.name == wanted || &s.id == wanted)
+        });
 
-        Self {
+        let mut app_state = Self {
             auth_state: AuthState::None,
-            focus_index,
+            auth_worker: None,
+            pam_message: None,
+            focus_index: 0,
             session: SelectField {
-                selected_idx: last_session.unwrap_or(0),
-                label: String::from("Session"),
+                selected_idx: 0,
+                label: if no_sessions {
+                    labels.no_sessions.clone()
+                } else {
+                    labels.session.clone()
+                },
                 index: 0,
                 items: sessions,
-                transform: |s: &Session| s.name.clone(),
+                transform: |s: &Session| s.label(),
             },
             username: SelectField {
                 index: 1,
-                label: String::from("Username"),
+                label: labels.username.clone(),
                 items: users,
                 transform: |s: &String| s.clone(),
                 selected_idx: last_user.unwrap_or(0),
             },
             password: TextField {
                 index: 2,
-                label: String::from("Password"),
+                label: labels.password.clone(),
                 input: Input::default(),
-                mask: Some(String::from("*")),
+                mask: Some(String::from(PASSWORD_MASK)),
             },
             max_focus_index: 2,
             hostname,
+            tick: 0,
+            command: TextField {
+                index: 0,
+                label: labels.command.clone(),
+                input: Input::default(),
+                mask: None,
+            },
+            editor: None,
+            exec_override: None,
+            default_session: preselected.unwrap_or(0),
+            state,
+            state_notice: None,
+            quit_at: None,
+            labels,
+        };
+
+        let mut session_known = app_state.select_user_session();
+        if let Some(idx) = preselected {
+            app_state.session.selected_idx = idx;
+            session_known = true;
+        }
+
+        // Go straight to the password when the user and their session are known.
+        if last_user.is_some() && session_known {
+            app_state.focus_index = app_state.password.index;
+        }
+        app_state
+    }
+
+    /// Selects the session the selected user logged in with last time, or their
+    /// last custom command. Returns whether a remembered choice was found.
+    ///
+    /// Choices made for the previously selected user are cleared, so that one
+    /// user's command line is never offered to the next.
+    pub fn select_user_session(&mut self) -> bool {
+        let user = self
+            .username
+            .get_value()
+            .and_then(|name| self.state.user(&name).cloned())
+            .unwrap_or_default();
+
+        self.command.input = Input::new(user.last_command.clone().unwrap_or_default());
+        self.exec_override = None;
+
+        let (idx, known) = match (
+            user.session_position(&self.session.items),
+            &user.last_command,
+        ) {
+            (Some(idx), _) => (idx, true),
+            (None, Some(_)) => (self.session.items.len() - 1, true),
+            (None, None) => (self.default_session, false),
+        };
+        self.session.selected_idx = idx;
+        known
+    }
+
+    /// Whether the trailing "Custom command…" session entry is selected.
+    pub fn custom_selected(&self) -> bool {
+        self.session.selected_idx + 1 == self.session.items.len()
+    }
+
+    /// The session to start: the selected entry with any edited command line, or
+    /// the typed custom command.
+    pub fn selected_session(&self) -> Option<Session> {
+        if self.custom_selected() {
+            return Session::custom(&self.command.get_value());
+        }
+
+        let mut session = self.session.get_value()?;
+        if let Some(exec) = self.edited_exec() {
+            session.exec = exec.clone();
+        }
+        Some(session)
+    }
+
+    /// The edited command line of the selected entry, if it was edited.
+    pub fn edited_exec(&self) -> Option<&Vec<String>> {
+        self.exec_override
+            .as_ref()
+            .filter(|(idx, _)| *idx == self.session.selected_idx)
+            .map(|(_, exec)| exec)
+    }
+
+    /// Opens the command editor on the selected session's command line.
+    pub fn open_editor(&mut self) {
+        if self.custom_selected() {
+            return;
         }
+        let Some(session) = self.selected_session() else {
+            return;
+        };
+
+        self.editor = Some(TextField {
+            index: 0,
+            label: self.labels.editor.clone(),
+            input: Input::new(join_exec(&session.exec)),
+            mask: None,
+        });
+    }
+
+    /// Closes the command editor, keeping the edited command line for the
+    /// selected entry. Returns false if the edit does not form a command.
+    pub fn close_editor(&mut self) -> bool {
+        let Some(exec) = self
+            .editor
+            .as_ref()
+            .and_then(|e| split_exec(&e.get_value()))
+        else {
+            return false;
+        };
+
+        self.exec_override = Some((self.session.selected_idx, exec));
+        self.editor = None;
+        true
     }
 
     pub fn focus_next(&mut self) {
@@ -79,4 +238,70 @@ impl AppState {
     pub fn focus_prev(&mut self) {
         self.focus_index = self.focus_index.saturating_sub(1);
     }
+
+    /// Turns the password field into the answer field for a PAM prompt.
+    pub fn show_prompt(&mut self, message: &str, secret: bool) {
+        let label = prompt_label(message);
+        self.password.label = if label.is_empty() {
+            self.labels.password.clone()
+        } else {
+            label.to_string()
+        };
+        self.password.mask = secret.then(|| String::from(PASSWORD_MASK));
+        self.password.input.reset();
+        self.focus_index = self.password.index;
+    }
+
+    /// Drops the running conversation, telling greetd to cancel it if `cancel` is set,
+    /// and puts the password field back into its initial state.
+    pub fn end_conversation(&mut self, cancel: bool) {
+        if let Some(worker) = self.auth_worker.take()
+            && cancel
+        {
+            worker.cancel();
+        }
+        self.pam_message = None;
+        self.password.label = self.labels.password.clone();
+        self.password.mask = Some(String::from(PASSWORD_MASK));
+        self.password.input.reset();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn switching_users_clears_the_previous_choice() {
+        let config: Config = toml::from_str("").unwrap();
+        let sessions = ["river", "sway"]
+            .map(|id| Session {
+                id: id.to_string(),
+                name: id.to_string(),
+                exec: vec![id.to_string()],
+                ..Session::default()
+            })
+            .to_vec();
+        let mut state = FerriteState::default();
+        state.record_login("alice", &Session::custom("rm -rf ~/scratch").unwrap());
+        state.record_login("carol", &sessions[1]);
+        let users = ["alice", "bob", "carol"].map(String::from).to_vec();
+
+        let mut app = AppState::new(sessions, users, String::new(), state, &config);
+        assert_eq!(app.username.get_value().as_deref(), Some("carol"));
+        assert_eq!(app.session.selected_idx, 1);
+
+        app.username.selected_idx = 0;
+        assert!(app.select_user_session());
+        let selected = app.session.get_value().unwrap();
+        assert_eq!(selected.id, "rm -rf ~/scratch");
+        assert_eq!(app.command.input.value(), "rm -rf ~/scratch");
+        app.exec_override = Some((0, vec![String::from("river")]));
+
+        app.username.selected_idx = 1;
+        assert!(!app.select_user_session());
+        assert_eq!(app.session.selected_idx, 0);
+        assert_eq!(app.command.input.value(), "");
+        assert!(app.exec_override.is_none());
+    }
 }
@@ -1,5 +1,8 @@
 use core::fmt;
-use std::{env, os::unix::net::UnixStream};
+use std::net::Shutdown;
+use std::path::Path;
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::{env, os::unix::net::UnixStream, thread};
 
 use color_eyre::Result;
 use greetd_ipc::codec::SyncCodec;
@@ -11,6 +14,7 @@ pub enum AuthError {
     Connection(String),
     Protocol(String),
     InvalidSession(String),
+    NoUser,
 }
 
 impl fmt::Display for AuthError {
@@ -20,68 +24,385 @@ impl fmt::Display for AuthError {
             AuthError::Connection(msg) => write!(f, "Connection error: {msg}"),
             AuthError::Protocol(msg) => write!(f, "Protocol error: {msg}"),
             AuthError::InvalidSession(msg) => write!(f, "Invalid session: {msg}"),
+            AuthError::NoUser => write!(f, "No user selected"),
         }
     }
 }
 
-pub fn authenticate(
-    username: &str,
-    password: &str,
-    session_cmd: &[String],
-) -> Result<(), AuthError> {
-    let socket_path = env::var("GREETD_SOCK").unwrap_or_else(|_| "/run/greetd.sock".to_string());
+impl std::error::Error for AuthError {}
+
+/// An informational or error message sent by PAM that needs no answer.
+#[derive(Debug, Clone)]
+pub struct PamMessage {
+    pub text: String,
+    pub error: bool,
+}
+
+/// A PAM prompt as a field label: `"Password: "` becomes `"Password"`.
+pub fn prompt_label(message: &str) -> &str {
+    message.trim().trim_end_matches(':').trim_end()
+}
 
-    let mut stream =
-        UnixStream::connect(&socket_path).map_err(|e| AuthError::Connection(e.to_string()))?;
+/// One step of the login conversation with greetd.
+#[derive(Debug)]
+pub enum AuthStep {
+    /// PAM asks a question; `secret` answers must not be echoed.
+    Prompt { message: String, secret: bool },
+    /// PAM shows a message; answer it with `respond(None)` to continue.
+    Message(PamMessage),
+    /// The user is authenticated and the session can be started.
+    Authenticated,
+}
+
+/// A greetd session that is being authenticated one prompt at a time.
+#[derive(Debug)]
+pub struct AuthSession {
+    stream: UnixStream,
+}
 
-    Request::CreateSession {
-        username: username.to_string(),
+impl AuthSession {
+    /// Connects to greetd at `socket`, or else at `$GREETD_SOCK`.
+    pub fn connect(socket: Option<&Path>) -> Result<Self, AuthError> {
+        let socket_path = match socket {
+            Some(socket) => socket.to_path_buf(),
+            None => env::var_os("GREETD_SOCK")
+                .unwrap_or_else(|| "/run/greetd.sock".into())
+                .into(),
+        };
+
+        let stream =
+            UnixStream::connect(&socket_path).map_err(|e| AuthError::Connection(e.to_string()))?;
+
+        Ok(Self { stream })
     }
-    .write_to(&mut stream)
-    .map_err(|e| AuthError::Protocol(e.to_string()))?;
 
-    loop {
-        match Response::read_from(&mut stream).map_err(|e| AuthError::Protocol(e.to_string()))? {
+    pub fn create(&mut self, username: &str) -> Result<AuthStep, AuthError> {
+        self.send(Request::CreateSession {
+            username: username.to_string(),
+        })
+    }
+
+    pub fn respond(&mut self, response: Option<String>) -> Result<AuthStep, AuthError> {
+        self.send(Request::PostAuthMessageResponse { response })
+    }
+
+    pub fn start(&mut self, session_cmd: &[String], env: &[String]) -> Result<(), AuthError> {
+        if session_cmd.is_empty() {
+            return Err(AuthError::InvalidSession(
+                "no session command provided".into(),
+            ));
+        }
+
+        Request::StartSession {
+            cmd: session_cmd.to_vec(),
+            env: env.to_vec(),
+        }
+        .write_to(&mut self.stream)
+        .map_err(|e| AuthError::Protocol(e.to_string()))?;
+
+        match Response::read_from(&mut self.stream)
+            .map_err(|e| AuthError::Protocol(e.to_string()))?
+        {
+            Response::Success => Ok(()),
+            Response::AuthMessage { .. } => Err(AuthError::InvalidSession(
+                "unexpected auth prompt after start_session".into(),
+            )),
+            Response::Error { description, .. } => Err(AuthError::Protocol(description)),
+        }
+    }
+
+    pub fn cancel(&mut self) {
+        let _ = Request::CancelSession.write_to(&mut self.stream);
+    }
+
+    fn send(&mut self, request: Request) -> Result<AuthStep, AuthError> {
+        request
+            .write_to(&mut self.stream)
+            .map_err(|e| AuthError::Protocol(e.to_string()))?;
+
+        match Response::read_from(&mut self.stream)
+            .map_err(|e| AuthError::Protocol(e.to_string()))?
+        {
             Response::AuthMessage {
-                auth_message_type, ..
-            } => {
-                let response = match auth_message_type {
-                    AuthMessageType::Visible | AuthMessageType::Secret => {
-                        Some(password.to_string())
-                    }
-                    _ => None,
+                auth_message_type,
+                auth_message,
+            } => Ok(match auth_message_type {
+                AuthMessageType::Visible => AuthStep::Prompt {
+                    message: auth_message,
+                    secret: false,
+                },
+                AuthMessageType::Secret => AuthStep::Prompt {
+                    message: auth_message,
+                    secret: true,
+                },
+                AuthMessageType::Info => AuthStep::Message(PamMessage {
+                    text: auth_message,
+                    error: false,
+                }),
+                AuthMessageType::Error => AuthStep::Message(PamMessage {
+                    text: auth_message,
+                    error: true,
+                }),
+            }),
+            Response::Success => Ok(AuthStep::Authenticated),
+            Response::Error { description, .. } => {
+                self.cancel();
+                Err(AuthError::AuthFailed(description))
+            }
+        }
+    }
+}
+
+/// Progress reported by an [`AuthWorker`].
+#[derive(Debug)]
+pub enum AuthEvent {
+    Prompt {
+        message: String,
+        secret: bool,
+    },
+    Message(PamMessage),
+    /// Text typed before the conversation started did not answer its first
+    /// prompt and was not sent.
+    PretypedUnused,
+    Started,
+    Failed(AuthError),
+}
+
+/// Runs a login conversation on a background thread so slow PAM modules never
+/// block drawing. Prompts arrive through [`AuthWorker::try_recv`] and are answered
+/// with [`AuthWorker::respond`].
+#[derive(Debug)]
+pub struct AuthWorker {
+    answers: Sender<Option<String>>,
+    events: Receiver<AuthEvent>,
+    control: UnixStream,
+}
+
+impl AuthWorker {
+    /// Starts authenticating `username`. A `pretyped` answer is used for the first
+    /// prompt if that prompt is secret, so a password-only login needs a single
+    /// Enter; PAM's prompt texts are translated and vary between modules, so they
+    /// are not compared. Any other first prompt is shown, after
+    /// [`AuthEvent::PretypedUnused`].
+    pub fn spawn(
+        username: String,
+        pretyped: Option<String>,
+        session_cmd: Vec<String>,
+        session_env: Vec<String>,
+        socket: Option<&Path>,
+    ) -> Result<Self, AuthError> {
+        let session = AuthSession::connect(socket)?;
+        let control = session
+            .stream
+            .try_clone()
+            .map_err(|e| AuthError::Connection(e.to_string()))?;
+
+        let (answers, answers_rx) = mpsc::channel();
+        let (events_tx, events) = mpsc::channel();
+
+        thread::spawn(move || {
+            let result = converse(
+                session,
+                &username,
+                pretyped,
+                (&session_cmd, &session_env),
+                &answers_rx,
+                &events_tx,
+            );
+            let _ = events_tx.send(match result {
+                Ok(()) => AuthEvent::Started,
+                Err(err) => AuthEvent::Failed(err),
+            });
+        });
+
+        Ok(Self {
+            answers,
+            events,
+            control,
+        })
+    }
+
+    pub fn respond(&self, answer: Option<String>) {
+        let _ = self.answers.send(answer);
+    }
+
+    pub fn try_recv(&self) -> Option<AuthEvent> {
+        match self.events.try_recv() {
+            Ok(event) => Some(event),
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) => Some(AuthEvent::Failed(AuthError::Protocol(
+                "authentication worker stopped".into(),
+            ))),
+        }
+    }
+
+    /// Cancels the greetd session and unblocks the worker, which then exits.
+    pub fn cancel(mut self) {
+        let _ = Request::CancelSession.write_to(&mut self.control);
+        let _ = self.control.shutdown(Shutdown::Both);
+    }
+}
+
+fn converse(
+    mut session: AuthSession,
+    username: &str,
+    mut pretyped: Option<String>,
+    (session_cmd, session_env): (&[String], &[String]),
+    answers: &Receiver<Option<String>>,
+    events: &Sender<AuthEvent>,
+) -> Result<(), AuthError> {
+    let mut step = session.create(username)?;
+
+    loop {
+        step = match step {
+            AuthStep::Message(message) => {
+                let _ = events.send(AuthEvent::Message(message));
+                session.respond(None)?
+            }
+            AuthStep::Prompt { secret: true, .. } if pretyped.is_some() => {
+                session.respond(pretyped.take())?
+            }
+            AuthStep::Prompt { message, secret } => {
+                // Once a prompt is shown, the field no longer holds the typed answer.
+                if pretyped.take().is_some() {
+                    let _ = events.send(AuthEvent::PretypedUnused);
+                }
+                let _ = events.send(AuthEvent::Prompt { message, secret });
+                // The UI hung up, so the conversation was cancelled.
+                let Ok(answer) = answers.recv() else {
+                    return Ok(());
                 };
+                session.respond(answer)?
+            }
+            AuthStep::Authenticated => return session.start(session_cmd, session_env),
+        };
+    }
+}
 
-                Request::PostAuthMessageResponse { response }
-                    .write_to(&mut stream)
-                    .map_err(|e| AuthError::Protocol(e.to_string()))?;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::net::UnixListener;
+    use std::time::Duration;
+
+    fn prompt(kind: AuthMessageType, message: &str) -> Response {
+        Response::AuthMessage {
+            auth_message_type: kind,
+            auth_message: message.to_string(),
+        }
+    }
+
+    fn create() -> Request {
+        Request::CreateSession {
+            username: String::from("alice"),
+        }
+    }
+
+    fn answer(response: Option<&str>) -> Request {
+        Request::PostAuthMessageResponse {
+            response: response.map(String::from),
+        }
+    }
+
+    /// Starts a login for alice against a stand-in for greetd, which checks each
+    /// request against `script` and sends the response paired with it, then
+    /// accepts the session.
+    fn login(pretyped: Option<&str>, script: Vec<(Request, Response)>) -> AuthWorker {
+        let dir = tempfile::tempdir().unwrap();
+        let socket = dir.path().join("greetd.sock");
+        let listener = UnixListener::bind(&socket).unwrap();
+        let start = Request::StartSession {
+            cmd: vec![String::from("sway")],
+            env: Vec::new(),
+        };
+
+        thread::spawn(move || {
+            let _dir = dir;
+            let (mut stream, _) = listener.accept().unwrap();
+            for (expected, response) in script.into_iter().chain([(start, Response::Success)]) {
+                let request = Request::read_from(&mut stream).unwrap();
+                assert_eq!(format!("{request:?}"), format!("{expected:?}"));
+                response.write_to(&mut stream).unwrap();
             }
-            Response::Success => break,
-            Response::Error { description, .. } => {
-                let _ = Request::CancelSession.write_to(&mut stream);
-                return Err(AuthError::AuthFailed(description));
+        });
+
+        AuthWorker::spawn(
+            String::from("alice"),
+            pretyped.map(String::from),
+            vec![String::from("sway")],
+            Vec::new(),
+            Some(&socket),
+        )
+        .unwrap()
+    }
+
+    fn next_event(worker: &AuthWorker) -> AuthEvent {
+        for _ in 0..500 {
+            if let Some(event) = worker.try_recv() {
+                return event;
             }
+            thread::sleep(Duration::from_millis(10));
         }
+        panic!("no event from the authentication worker");
     }
 
-    if session_cmd.is_empty() {
-        return Err(AuthError::InvalidSession(
-            "no session command provided".into(),
-        ));
+    #[test]
+    fn prompt_labels() {
+        assert_eq!(prompt_label("Password: "), "Password");
+        assert_eq!(prompt_label("  OTP code :"), "OTP code");
+        assert_eq!(prompt_label(""), "");
     }
 
-    Request::StartSession {
-        cmd: session_cmd.to_vec(),
-        env: Vec::new(),
+    #[test]
+    fn pretyped_answers_the_first_secret_prompt() {
+        // Prompt texts differ from the "Password" field label.
+        for message in [
+            "Passwort: ",
+            "パスワード:",
+            "Password for alice@EXAMPLE.COM: ",
+        ] {
+            let worker = login(
+                Some("hunter2"),
+                vec![
+                    (create(), prompt(AuthMessageType::Info, "Welcome")),
+                    (answer(None), prompt(AuthMessageType::Secret, message)),
+                    (answer(Some("hunter2")), Response::Success),
+                ],
+            );
+            assert!(matches!(next_event(&worker), AuthEvent::Message(m) if m.text == "Welcome"));
+            let event = next_event(&worker);
+            assert!(matches!(event, AuthEvent::Started), "{message}: {event:?}");
+        }
     }
-    .write_to(&mut stream)
-    .map_err(|e| AuthError::Protocol(e.to_string()))?;
 
-    match Response::read_from(&mut stream).map_err(|e| AuthError::Protocol(e.to_string()))? {
-        Response::Success => Ok(()),
-        Response::AuthMessage { .. } => Err(AuthError::InvalidSession(
-            "unexpected auth prompt after start_session".into(),
-        )),
-        Response::Error { description, .. } => Err(AuthError::Protocol(description)),
+    #[test]
+    fn pretyped_is_not_sent_to_another_first_prompt() {
+        let worker = login(
+            Some("hunter2"),
+            vec![
+                (
+                    create(),
+                    prompt(AuthMessageType::Visible, "Verification code: "),
+                ),
+                (
+                    answer(Some("123456")),
+                    prompt(AuthMessageType::Secret, "Password: "),
+                ),
+                (answer(Some("hunter2")), Response::Success),
+            ],
+        );
+
+        assert!(matches!(next_event(&worker), AuthEvent::PretypedUnused));
+        assert!(matches!(
+            next_event(&worker),
+            AuthEvent::Prompt { secret: false, .. }
+        ));
+        worker.respond(Some(String::from("123456")));
+        assert!(matches!(
+            next_event(&worker),
+            AuthEvent::Prompt { secret: true, .. }
+        ));
+        worker.respond(Some(String::from("hunter2")));
+        assert!(matches!(next_event(&worker), AuthEvent::Started));
     }
 }
@@ -0,0 +1,338 @@
+use std::{
+    fmt,
+    fs::{self, Metadata},
+    os::unix::fs::MetadataExt,
+    path::{Path, PathBuf},
+};
+
+use crate::cli::Cli;
+use crate::config::{Config, config_files, load_config, read_table};
+use crate::desktop::try_exec;
+use crate::log::LOG_PATH;
+use crate::state::{lock_path, parse_state, state_path};
+use crate::theme::load_theme;
+use crate::util::{get_login_users, read_sessions, user_ids};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Level {
+    Ok,
+    Warning,
+    Error,
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Level::Ok => f.pad("ok"),
+            Level::Warning => f.pad("warning"),
+            Level::Error => f.pad("error"),
+        }
+    }
+}
+
+/// Collects the findings of `ferrite check`, printing them as they come.
+#[derive(Default)]
+struct Report {
+    warnings: usize,
+    errors: usize,
+}
+
+impl Report {
+    fn add(&mut self, level: Level, message: impl fmt::Display) {
+        match level {
+            Level::Ok => {}
+            Level::Warning => self.warnings += 1,
+            Level::Error => self.errors += 1,
+        }
+        println!("{level:<8} {message}");
+    }
+}
+
+/// Checks everything the greeter reads at startup without starting it, for
+/// `greeter` as the user greetd runs it as. Returns the exit code: non-zero if
+/// anything would break the greeter.
+pub fn run(cli: &Cli, greeter: &str) -> i32 {
+    let mut report = Report::default();
+
+    let config = check_config(cli, &mut report);
+    check_programs(&config, &mut report);
+    check_sessions(&config, &mut report);
+    check_users(&config, &mut report);
+    check_state(&config, greeter, &mut report);
+
+    println!(
+        "\n{} error(s), {} warning(s)",
+        report.errors, report.warnings
+    );
+    i32::from(report.errors > 0)
+}
+
+fn check_config(cli: &Cli, report: &mut Report) -> Config {
+    let errors = report.errors;
+    match config_files(cli.config.as_deref()) {
+        Ok(files) => {
+            for (i, path) in files.iter().enumerate() {
+                match read_table(path) {
+                    Ok(_) if i == 0 && !path.exists() && cli.config.is_none() => report.add(
+                        Level::Ok,
+                        format!("{}: not present, using defaults", path.display()),
+                    ),
+                    Ok(_) if !path.exists() => {
+                        report.add(Level::Error, format!("{}: not found", path.display()))
+                    }
+                    Ok(_) => report.add(Level::Ok, format!("{}: parsed", path.display())),
+                    Err(e) => report.add(Level::Error, format!("{e:#}")),
+                }
+            }
+        }
+        Err(e) => report.add(Level::Error, format!("{e:#}")),
+    }
+
+    let theme = cli
+        .theme
+        .as_ref()
+        .and_then(|theme| match load_theme(theme) {
+            Ok(loaded) => {
+                report.add(Level::Ok, format!("theme {}: valid", theme.display()));
+                Some(loaded)
+            }
+            Err(e) => {
+                report.add(Level::Error, format!("theme {}: {e:#}", theme.display()));
+                report.add(Level::Warning, "the greeter would use the configured theme");
+                None
+            }
+        });
+
+    // Files that failed above would only be reported again.
+    let loaded = load_config(cli.config.as_deref());
+    let mut config = match loaded {
+        Ok(config) => {
+            report.add(Level::Ok, "configuration is valid");
+            config
+        }
+        Err(e) => {
+            if report.errors == errors {
+                report.add(Level::Error, format!("{e:#}"));
+            }
+            report.add(
+                Level::Warning,
+                "the greeter would fall back to the default configuration",
+            );
+            Config::default()
+        }
+    };
+    if let Some(theme) = theme {
+        config.theme = theme;
+    }
+    cli.apply(&mut config);
+    config
+}
+
+/// Commands the greeter runs itself; sessions are checked by discovery.
+fn check_programs(config: &Config, report: &mut Report) {
+    let mut programs = vec![
+        ("shutdown command", &config.power.shutdown),
+        ("reboot command", &config.power.reboot),
+        ("Wayland wrapper", &config.wrappers.wayland),
+        ("X11 wrapper", &config.wrappers.x11),
+        ("TTY wrapper", &config.wrappers.tty),
+    ];
+    if config.x11.native {
+        programs.push(("X server", &config.x11.server));
+    }
+
+    for (what, cmd) in programs {
+        let Some(program) = cmd.first() else {
+            continue;
+        };
+        if try_exec(program) {
+            report.add(Level::Ok, format!("{what}: {program}"));
+        } else {
+            report.add(
+                Level::Warning,
+                format!("{what}: {program} is not installed"),
+            );
+        }
+    }
+}
+
+fn check_sessions(config: &Config, report: &mut Report) {
+    let sessions = match read_sessions(config) {
+        Ok(sessions) => sessions,
+        Err(e) => {
+            report.add(Level::Error, format!("session discovery failed: {e:#}"));
+            return;
+        }
+    };
+
+    if sessions.is_empty() {
+        report.add(
+            Level::Warning,
+            "no sessions found; the fallback session will be offered",
+        );
+    }
+    for session in &sessions {
+        report.add(Level::Ok, format!("session: {}", session.label()));
+    }
+
+    if let Some(wanted) = &config.defaults.session
+        && !sessions
+            .iter()
+            .any(|s| &s.name == wanted || &s.id == wanted)
+    {
+        report.add(
+            Level::Warning,
+            format!("preselected session {wanted} not found"),
+        );
+    }
+}
+
+fn check_users(config: &Config, report: &mut Report) {
+    let users = match get_login_users() {
+        Ok(users) => users,
+        Err(e) => {
+            report.add(Level::Error, format!("reading users failed: {e:#}"));
+            return;
+        }
+    };
+
+    if users.is_empty() {
+        report.add(Level::Error, "no login users found");
+    } else {
+        report.add(Level::Ok, format!("users: {}", users.join(", ")));
+    }
+
+    if let Some(wanted) = &config.defaults.user
+        && !users.contains(wanted)
+    {
+        report.add(
+            Level::Warning,
+            format!("preselected user {wanted} not found"),
+        );
+    }
+}
+
+fn check_state(config: &Config, greeter: &str, report: &mut Report) {
+    let Some((uid, gid)) = user_ids(greeter) else {
+        report.add(
+            Level::Error,
+            format!("greeter user {greeter} not found, use --greeter-user"),
+        );
+        return;
+    };
+
+    let path = state_path(&config.state);
+    let dir = path.parent().unwrap_or(Path::new("/"));
+    match writable_dir(dir, uid, gid) {
+        Ok(()) => report.add(Level::Ok, format!("{greeter} can write {}", dir.display())),
+        Err(e) => report.add(Level::Error, format!("{e}; logins will not be remembered")),
+    }
+
+    // Read with this command's rights, usually root's, so the greeter's are
+    // checked separately.
+    match fs::read_to_string(&path) {
+        Ok(contents) => match (parse_state(&contents), file_access(&path, uid, gid, 0o4)) {
+            (_, Err(e)) => report.add(Level::Error, format!("{e}; logins will not be saved")),
+            (Ok(_), Ok(())) => report.add(Level::Ok, format!("{}: readable", path.display())),
+            (Err(e), Ok(())) => report.add(
+                Level::Warning,
+                format!("{}: {e:#}; it will be moved aside", path.display()),
+            ),
+        },
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => report.add(Level::Error, format!("{}: {e}", path.display())),
+    }
+
+    // The lock file is opened for writing before every save.
+    let lock = lock_path(&path);
+    if lock.exists()
+        && let Err(e) = file_access(&lock, uid, gid, 0o2)
+    {
+        report.add(Level::Error, format!("{e}; logins will not be saved"));
+    }
+
+    let log_dir = Path::new(LOG_PATH).parent().unwrap_or(Path::new("/"));
+    if let Err(e) = writable_dir(log_dir, uid, gid) {
+        report.add(Level::Warning, format!("{e}; problems will not be logged"));
+    }
+}
+
+/// Whether `uid` (with primary group `gid`) can create files in `dir`, or create
+/// `dir` itself.
+fn writable_dir(dir: &Path, uid: u32, gid: u32) -> Result<(), String> {
+    let mut existing: PathBuf = dir.to_path_buf();
+    while !existing.exists() {
+        match existing.parent() {
+            Some(parent) => existing = parent.to_path_buf(),
+            None => break,
+        }
+    }
+
+    // Creating files in a directory takes write and search permission.
+    file_access(&existing, uid, gid, 0o3)
+}
+
+/// Whether `uid` (with primary group `gid`) has the permissions in `need` (`0o4`
+/// read, `0o2` write, `0o1` search) on the existing `path`. Supplementary groups
+/// and ACLs are not considered.
+fn file_access(path: &Path, uid: u32, gid: u32, need: u32) -> Result<(), String> {
+    let meta = fs::metadata(path).map_err(|e| format!("{}: {e}", path.display()))?;
+    if uid == 0 || permission_bits(&meta, uid, gid) & need == need {
+        return Ok(());
+    }
+
+    let access = if need & 0o2 != 0 { "write" } else { "read" };
+    Err(format!(
+        "uid {uid} cannot {access} {} (owner {}:{}, mode {:o})",
+        path.display(),
+        meta.uid(),
+        meta.gid(),
+        meta.mode() & 0o7777
+    ))
+}
+
+/// The permission bits that apply to `uid` with primary group `gid`.
+fn permission_bits(meta: &Metadata, uid: u32, gid: u32) -> u32 {
+    let mode = meta.mode();
+    if meta.uid() == uid {
+        mode >> 6
+    } else if meta.gid() == gid {
+        mode >> 3
+    } else {
+        mode
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+
+    #[test]
+    fn file_access_uses_the_greeters_rights() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state.json");
+        fs::write(&path, "{}").unwrap();
+        let meta = fs::metadata(&path).unwrap();
+        let (owner, group) = (meta.uid(), meta.gid());
+        let other = owner.wrapping_add(1).max(1);
+        let set_mode = |mode| fs::set_permissions(&path, fs::Permissions::from_mode(mode)).unwrap();
+
+        set_mode(0o600);
+        assert!(file_access(&path, owner, group, 0o6).is_ok());
+        let err = file_access(&path, other, group.wrapping_add(1), 0o4).unwrap_err();
+        assert!(err.contains("cannot read"), "{err}");
+        assert!(file_access(&path, other, group, 0o4).is_err());
+        assert!(file_access(&path, 0, 0, 0o6).is_ok());
+
+        set_mode(0o640);
+        assert!(file_access(&path, other, group, 0o4).is_ok());
+        let err = file_access(&path, other, group, 0o2).unwrap_err();
+        assert!(
+            err.contains("cannot write") && err.contains("mode 640"),
+            "{err}"
+        );
+
+        assert!(file_access(&dir.path().join("missing"), other, group, 0o4).is_err());
+    }
+}
@@ -0,0 +1,100 @@
+use clap::{Parser, Subcommand};
+use std::{env, path::PathBuf};
+
+use crate::config::Config;
+use crate::state::STATE_FILE_ENV;
+use crate::x11;
+
+/// A TUI greeter for greetd. Options override the configuration file.
+#[derive(Parser, Debug)]
+#[command(version)]
+pub struct Cli {
+    /// Configuration file, with drop-ins in the `.d` directory next to it
+    /// [default: /etc/ferrite/config.toml]
+    #[arg(long, value_name = "FILE", global = true)]
+    pub config: Option<PathBuf>,
+
+    /// File keeping each user's last session
+    #[arg(long, value_name = "FILE", global = true)]
+    pub state_file: Option<PathBuf>,
+
+    /// Text shown at the top of the screen
+    #[arg(long, value_name = "TEXT")]
+    pub greeting: Option<String>,
+
+    /// Built-in theme (default, nord, gruvbox, dracula) or TOML theme file
+    #[arg(long, value_name = "NAME|FILE", global = true)]
+    pub theme: Option<PathBuf>,
+
+    /// User to preselect, instead of the last one to log in
+    #[arg(long, value_name = "NAME")]
+    pub user: Option<String>,
+
+    /// Session to preselect, by name or desktop-file ID
+    #[arg(long, value_name = "SESSION")]
+    pub session: Option<String>,
+
+    /// greetd's socket [default: $GREETD_SOCK]
+    #[arg(long, value_name = "PATH")]
+    pub socket: Option<PathBuf>,
+
+    /// Hide the shutdown and reboot actions
+    #[arg(long)]
+    pub no_power: bool,
+
+    /// Ask line by line instead of drawing the full-screen UI
+    #[arg(long)]
+    pub line_mode: bool,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Checks the configuration, session and user discovery and the state directory,
+    /// then exits non-zero if the greeter would not work
+    Check {
+        /// User greetd runs the greeter as
+        #[arg(long, value_name = "NAME", default_value = "greeter")]
+        greeter_user: String,
+    },
+
+    /// Runs an X server and the given X11 client; started by greetd for native X11 sessions
+    #[command(name = x11::SESSION_ARG, hide = true)]
+    X11Session {
+        #[arg(required = true, trailing_var_arg = true, allow_hyphen_values = true)]
+        client: Vec<String>,
+    },
+}
+
+impl Cli {
+    /// Applies the options (and `FERRITE_STATE_FILE`) over the loaded configuration.
+    pub fn apply(&self, config: &mut Config) {
+        if let Some(path) = env::var_os(STATE_FILE_ENV).filter(|path| !path.is_empty()) {
+            config.state.path = path.into();
+        }
+        if let Some(path) = &self.state_file {
+            config.state.path = path.clone();
+        }
+        if let Some(greeting) = &self.greeting {
+            config.ui.greeting = Some(greeting.clone());
+        }
+        if let Some(user) = &self.user {
+            config.defaults.user = Some(user.clone());
+        }
+        if let Some(session) = &self.session {
+            config.defaults.session = Some(session.clone());
+        }
+        if let Some(socket) = &self.socket {
+            config.greetd.socket = Some(socket.clone());
+        }
+        if self.no_power {
+            config.power.shutdown.clear();
+            config.power.reboot.clear();
+        }
+        if self.line_mode {
+            config.ui.line_mode = Some(true);
+        }
+    }
+}
@@ -0,0 +1,593 @@
+use anyhow::{Context, bail};
+use ratatui::crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use serde::Deserialize;
+use std::{
+    collections::BTreeMap,
+    fmt, fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+use toml::{Table, Value};
+
+use crate::theme::{ColorDepth, Theme, ascii_text};
+
+const CONFIG_PATH: &str = "/etc/ferrite/config.toml";
+
+#[derive(Deserialize, Default, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Extra environment variables passed to every session.
+    pub env: BTreeMap<String, String>,
+    /// Per-session settings, keyed by desktop-file ID (e.g. `sway`).
+    pub sessions: BTreeMap<String, SessionConfig>,
+    pub discovery: DiscoveryConfig,
+    pub wrappers: WrapperConfig,
+    pub x11: X11Config,
+    pub state: StateConfig,
+    pub ui: UiConfig,
+    /// The `[theme]` table: a built-in theme named by `base`, with overrides.
+    #[serde(deserialize_with = "crate::theme::deserialize")]
+    pub theme: Theme,
+    pub labels: LabelConfig,
+    pub keys: KeyConfig,
+    pub power: PowerConfig,
+    pub defaults: DefaultsConfig,
+    pub greetd: GreetdConfig,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct DiscoveryConfig {
+    /// greetd's list of session commands, one per line, shared with other greeters.
+    pub environments_file: Option<PathBuf>,
+    /// Command offered when no sessions are found, instead of the user's login shell.
+    pub fallback_command: Option<String>,
+    /// Directories searched for Wayland session entries, replacing the XDG search.
+    pub wayland_dirs: Option<Vec<PathBuf>>,
+    /// Directories searched for X11 session entries, replacing the XDG search.
+    pub x11_dirs: Option<Vec<PathBuf>>,
+}
+
+impl Default for DiscoveryConfig {
+    fn default() -> Self {
+        Self {
+            environments_file: Some(PathBuf::from("/etc/greetd/environments")),
+            fallback_command: None,
+            wayland_dirs: None,
+            x11_dirs: None,
+        }
+    }
+}
+
+/// Commands prepended to a session's argv, per session kind. A wrapper ending in
+/// `-c` (e.g. `["/bin/sh", "-l", "-c"]`) gets `exec <command>` as one shell string.
+#[derive(Deserialize, Default, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct WrapperConfig {
+    pub wayland: Vec<String>,
+    pub x11: Vec<String>,
+    /// Used for TTY sessions and custom commands.
+    pub tty: Vec<String>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct X11Config {
+    /// Start X11 sessions with ferrite's own X server launcher instead of the
+    /// `x11` wrapper.
+    pub native: bool,
+    /// X server command; the display, VT and `-auth` arguments are appended.
+    pub server: Vec<String>,
+}
+
+impl Default for X11Config {
+    fn default() -> Self {
+        Self {
+            native: false,
+            server: ["/usr/bin/Xorg", "-nolisten", "tcp", "-keeptty"]
+                .map(String::from)
+                .to_vec(),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct StateConfig {
+    /// Where remembered choices are kept; `--state-file` and `FERRITE_STATE_FILE`
+    /// take precedence.
+    pub path: PathBuf,
+    /// Keep a separate file per seat, named after `XDG_SEAT` (`state-seat0.json`).
+    pub per_seat: bool,
+}
+
+impl Default for StateConfig {
+    fn default() -> Self {
+        Self {
+            path: PathBuf::from("/var/lib/ferrite/state.json"),
+            per_seat: false,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct UiConfig {
+    /// Size of the login box.
+    pub width: u16,
+    pub height: u16,
+    /// Text shown at the top of the screen.
+    pub greeting: Option<String>,
+    /// Set the Linux console's palette from the theme when running on a VT.
+    pub console_palette: bool,
+    /// Colours the terminal can show (`truecolor`, `256`, `16` or `mono`),
+    /// detected from the terminal if unset.
+    pub colors: Option<ColorDepth>,
+    /// Draw with ASCII only; the default for locales that are not UTF-8.
+    pub ascii: Option<bool>,
+    /// Ask line by line instead of drawing the full-screen UI; the default for
+    /// dumb and VT100-style terminals.
+    pub line_mode: Option<bool>,
+}
+
+impl Default for UiConfig {
+    fn default() -> Self {
+        Self {
+            width: 55,
+            height: 15,
+            greeting: None,
+            console_palette: true,
+            colors: None,
+            ascii: None,
+            line_mode: None,
+        }
+    }
+}
+
+/// Preselected choices, taking precedence over the remembered ones.
+#[derive(Deserialize, Default, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct DefaultsConfig {
+    pub user: Option<String>,
+    /// Session name or desktop-file ID.
+    pub session: Option<String>,
+}
+
+#[derive(Deserialize, Default, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct GreetdConfig {
+    /// greetd's socket, instead of `$GREETD_SOCK`.
+    pub socket: Option<PathBuf>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct LabelConfig {
+    pub title: String,
+    pub authenticating: String,
+    pub failed: String,
+    pub session: String,
+    /// Session field title when no sessions were discovered.
+    pub no_sessions: String,
+    pub username: String,
+    pub password: String,
+    pub command: String,
+    /// The session entry that turns into a command input.
+    pub custom_entry: String,
+    /// Title of the command line editor.
+    pub editor: String,
+    /// Shown before a session's edited command line.
+    pub edited: String,
+    /// Shown when the login asks something else first, so the typed password
+    /// was not sent.
+    pub pretyped_unused: String,
+    pub shutdown: String,
+    pub reboot: String,
+}
+
+impl LabelConfig {
+    /// The labels in 7-bit ASCII, for consoles without the glyphs for others.
+    pub fn to_ascii(&self) -> LabelConfig {
+        LabelConfig {
+            title: ascii_text(&self.title),
+            authenticating: ascii_text(&self.authenticating),
+            failed: ascii_text(&self.failed),
+            session: ascii_text(&self.session),
+            no_sessions: ascii_text(&self.no_sessions),
+            username: ascii_text(&self.username),
+            password: ascii_text(&self.password),
+            command: ascii_text(&self.command),
+            custom_entry: ascii_text(&self.custom_entry),
+            editor: ascii_text(&self.editor),
+            edited: ascii_text(&self.edited),
+            pretyped_unused: ascii_text(&self.pretyped_unused),
+            shutdown: ascii_text(&self.shutdown),
+            reboot: ascii_text(&self.reboot),
+        }
+    }
+}
+
+impl Default for LabelConfig {
+    fn default() -> Self {
+        Self {
+            title: String::from("Authenticate"),
+            authenticating: String::from("Authenticating"),
+            failed: String::from("Authentication Failed"),
+            session: String::from("Session"),
+            no_sessions: String::from("Session (no sessions found)"),
+            username: String::from("Username"),
+            password: String::from("Password"),
+            command: String::from("Command"),
+            custom_entry: String::from("Custom command…"),
+            editor: String::from("Command (Enter: launch, Esc: cancel)"),
+            edited: String::from("Edited"),
+            pretyped_unused: String::from("The typed password was not sent"),
+            shutdown: String::from("Shutdown"),
+            reboot: String::from("Reboot"),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct KeyConfig {
+    pub shutdown: KeyBinding,
+    pub reboot: KeyBinding,
+    /// Opens the command line editor on the session field.
+    pub edit: KeyBinding,
+    /// Cancels the running login, or else quits.
+    pub cancel: KeyBinding,
+}
+
+impl Default for KeyConfig {
+    fn default() -> Self {
+        Self {
+            shutdown: KeyBinding::new(KeyCode::F(1), KeyModifiers::NONE),
+            reboot: KeyBinding::new(KeyCode::F(2), KeyModifiers::NONE),
+            edit: KeyBinding::new(KeyCode::Char('e'), KeyModifiers::CONTROL),
+            cancel: KeyBinding::new(KeyCode::Esc, KeyModifiers::NONE),
+        }
+    }
+}
+
+/// A key with modifiers, written like `F1`, `Esc` or `Ctrl+E`. Modifiers must
+/// match exactly, and characters need Ctrl or Alt so that typing never
+/// triggers a binding.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(try_from = "String")]
+pub struct KeyBinding {
+    pub code: KeyCode,
+    pub modifiers: KeyModifiers,
+}
+
+impl KeyBinding {
+    pub fn new(code: KeyCode, modifiers: KeyModifiers) -> Self {
+        Self { code, modifiers }
+    }
+
+    pub fn matches(&self, key: &KeyEvent) -> bool {
+        key.code == self.code && key.modifiers == self.modifiers
+    }
+}
+
+const NAMED_KEYS: [(&str, KeyCode); 14] = [
+    ("Esc", KeyCode::Esc),
+    ("Enter", KeyCode::Enter),
+    ("Tab", KeyCode::Tab),
+    ("Backspace", KeyCode::Backspace),
+    ("Delete", KeyCode::Delete),
+    ("Insert", KeyCode::Insert),
+    ("Home", KeyCode::Home),
+    ("End", KeyCode::End),
+    ("PageUp", KeyCode::PageUp),
+    ("PageDown", KeyCode::PageDown),
+    ("Up", KeyCode::Up),
+    ("Down", KeyCode::Down),
+    ("Left", KeyCode::Left),
+    ("Right", KeyCode::Right),
+];
+
+impl FromStr for KeyBinding {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        let mut parts: Vec<&str> = s.split('+').map(str::trim).collect();
+        let key = parts.pop().unwrap_or_default();
+
+        let mut modifiers = KeyModifiers::NONE;
+        for part in parts {
+            modifiers |= match part.to_ascii_lowercase().as_str() {
+                "ctrl" | "control" => KeyModifiers::CONTROL,
+                "alt" => KeyModifiers::ALT,
+                "shift" => KeyModifiers::SHIFT,
+                _ => bail!("unknown modifier `{part}` in `{s}`"),
+            };
+        }
+
+        let mut chars = key.chars();
+        let code = match (chars.next(), chars.next()) {
+            (Some(' '), None) => KeyCode::Char(' '),
+            // Terminals send Shift+E as an upper-case character.
+            (Some(c), None) if modifiers.contains(KeyModifiers::SHIFT) => {
+                KeyCode::Char(c.to_ascii_uppercase())
+            }
+            (Some(c), None) => KeyCode::Char(c.to_ascii_lowercase()),
+            _ => match NAMED_KEYS
+                .iter()
+                .find(|(name, _)| name.eq_ignore_ascii_case(key))
+            {
+                Some((_, code)) => *code,
+                None if key.eq_ignore_ascii_case("space") => KeyCode::Char(' '),
+                None => match key.strip_prefix(['F', 'f']).and_then(|n| n.parse().ok()) {
+                    Some(n) if (1..=24).contains(&n) => KeyCode::F(n),
+                    _ => bail!("unknown key `{key}` in `{s}`"),
+                },
+            },
+        };
+
+        if matches!(code, KeyCode::Char(_))
+            && !modifiers.intersects(KeyModifiers::CONTROL | KeyModifiers::ALT)
+        {
+            bail!("`{s}` would be typed into the fields, add Ctrl or Alt");
+        }
+
+        Ok(Self { code, modifiers })
+    }
+}
+
+impl TryFrom<String> for KeyBinding {
+    type Error = anyhow::Error;
+
+    fn try_from(s: String) -> anyhow::Result<Self> {
+        s.parse()
+    }
+}
+
+impl fmt::Display for KeyBinding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (modifier, name) in [
+            (KeyModifiers::CONTROL, "Ctrl+"),
+            (KeyModifiers::ALT, "Alt+"),
+            (KeyModifiers::SHIFT, "Shift+"),
+        ] {
+            if self.modifiers.contains(modifier) {
+                f.write_str(name)?;
+            }
+        }
+
+        match self.code {
+            KeyCode::Char(' ') => f.write_str("Space"),
+            KeyCode::Char(c) => write!(f, "{}", c.to_ascii_uppercase()),
+            KeyCode::F(n) => write!(f, "F{n}"),
+            code => match NAMED_KEYS.iter().find(|(_, named)| *named == code) {
+                Some((name, _)) => f.write_str(name),
+                None => write!(f, "{code:?}"),
+            },
+        }
+    }
+}
+
+/// Commands run for the power keys. An empty command turns the action off.
+#[derive(Deserialize, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct PowerConfig {
+    pub shutdown: Vec<String>,
+    pub reboot: Vec<String>,
+}
+
+impl Default for PowerConfig {
+    fn default() -> Self {
+        Self {
+            shutdown: ["systemctl", "poweroff"].map(String::from).to_vec(),
+            reboot: ["systemctl", "reboot"].map(String::from).to_vec(),
+        }
+    }
+}
+
+#[derive(Deserialize, Default, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct SessionConfig {
+    /// Extra environment variables for this session only.
+    pub env: BTreeMap<String, String>,
+    /// Whether the wrapper for the session's kind applies (default: true).
+    pub wrap: Option<bool>,
+}
+
+/// Reads a TOML file as a table; a missing file is an empty one.
+pub fn read_table(path: &Path) -> anyhow::Result<Table> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Table::new()),
+        Err(e) => return Err(e).with_context(|| format!("reading {}", path.display())),
+    };
+
+    toml::from_str(&contents).with_context(|| format!("parsing {}", path.display()))
+}
+
+/// Merges `overlay` into `base`: tables are merged key by key, anything else
+/// (including arrays) is replaced.
+pub fn merge(base: &mut Table, overlay: Table) {
+    for (key, value) in overlay {
+        match (base.get_mut(&key), value) {
+            (Some(Value::Table(base)), Value::Table(overlay)) => merge(base, overlay),
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+/// The `*.toml` files in the drop-in directory, in lexical order.
+fn drop_ins(dir: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e).with_context(|| format!("reading {}", dir.display())),
+    };
+
+    let mut paths: Vec<PathBuf> = entries
+        .map_while(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().and_then(|v| v.to_str()) == Some("toml"))
+        .collect();
+    paths.sort();
+    Ok(paths)
+}
+
+/// The config file at `path` (by default `/etc/ferrite/config.toml`) followed by
+/// the drop-ins from the directory next to it (`config.d`), in merge order.
+pub fn config_files(path: Option<&Path>) -> anyhow::Result<Vec<PathBuf>> {
+    let path = path.unwrap_or(Path::new(CONFIG_PATH));
+    let mut files = vec![path.to_path_buf()];
+    files.extend(drop_ins(&path.with_extension("d"))?);
+    Ok(files)
+}
+
+/// Loads the config files with the drop-ins merged over the main file. Only the
+/// default config file may be missing.
+pub fn load_config(path: Option<&Path>) -> anyhow::Result<Config> {
+    if let Some(path) = path
+        && !path.exists()
+    {
+        bail!("{} not found", path.display());
+    }
+
+    let mut table = Table::new();
+    for path in config_files(path)? {
+        merge(&mut table, read_table(&path)?);
+    }
+
+    Config::deserialize(Value::Table(table)).context("invalid configuration")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(code: KeyCode, modifiers: KeyModifiers) -> KeyEvent {
+        KeyEvent::new(code, modifiers)
+    }
+
+    #[test]
+    fn parse_key_bindings() {
+        let binding = |s: &str| s.parse::<KeyBinding>().unwrap();
+        assert_eq!(
+            binding("F1"),
+            KeyBinding::new(KeyCode::F(1), KeyModifiers::NONE)
+        );
+        assert_eq!(
+            binding("esc"),
+            KeyBinding::new(KeyCode::Esc, KeyModifiers::NONE)
+        );
+        assert_eq!(
+            binding("Ctrl+E"),
+            KeyBinding::new(KeyCode::Char('e'), KeyModifiers::CONTROL)
+        );
+        assert_eq!(
+            binding("alt + space"),
+            KeyBinding::new(KeyCode::Char(' '), KeyModifiers::ALT)
+        );
+        assert_eq!(
+            binding("Ctrl+Shift+E"),
+            KeyBinding::new(
+                KeyCode::Char('E'),
+                KeyModifiers::CONTROL | KeyModifiers::SHIFT
+            )
+        );
+        assert_eq!(binding("Ctrl+Shift+E").to_string(), "Ctrl+Shift+E");
+    }
+
+    #[test]
+    fn reject_bad_key_bindings() {
+        for s in ["s", "Shift+S", "Space", "F25", "Hyper+E", "Ctrl+Nope", ""] {
+            assert!(s.parse::<KeyBinding>().is_err(), "{s}");
+        }
+    }
+
+    #[test]
+    fn key_bindings_match_modifiers_exactly() {
+        let f1: KeyBinding = "F1".parse().unwrap();
+        assert!(f1.matches(&key(KeyCode::F(1), KeyModifiers::NONE)));
+        assert!(!f1.matches(&key(KeyCode::F(1), KeyModifiers::CONTROL)));
+
+        let edit: KeyBinding = "Ctrl+E".parse().unwrap();
+        assert!(edit.matches(&key(KeyCode::Char('e'), KeyModifiers::CONTROL)));
+        assert!(!edit.matches(&key(KeyCode::Char('e'), KeyModifiers::NONE)));
+        assert!(!edit.matches(&key(
+            KeyCode::Char('e'),
+            KeyModifiers::CONTROL | KeyModifiers::ALT
+        )));
+
+        let shifted: KeyBinding = "Alt+Shift+E".parse().unwrap();
+        assert!(shifted.matches(&key(
+            KeyCode::Char('E'),
+            KeyModifiers::ALT | KeyModifiers::SHIFT
+        )));
+    }
+
+    #[test]
+    fn merge_tables_and_replace_values() {
+        let mut base: Table = toml::from_str(
+            "[ui]\nwidth = 55\ngreeting = \"Hi\"\n[power]\nshutdown = [\"poweroff\"]\n",
+        )
+        .unwrap();
+        let overlay: Table =
+            toml::from_str("title = \"x\"\n[ui]\nwidth = 70\n[power]\nshutdown = []\n").unwrap();
+        merge(&mut base, overlay);
+
+        let expected: Table = toml::from_str(
+            "title = \"x\"\n[ui]\nwidth = 70\ngreeting = \"Hi\"\n[power]\nshutdown = []\n",
+        )
+        .unwrap();
+        assert_eq!(base, expected);
+
+        // A table replaces a plain value and the other way round.
+        let mut base: Table = toml::from_str("a = 1\n[b]\nc = 2\n").unwrap();
+        merge(&mut base, toml::from_str("b = 3\n[a]\nc = 4\n").unwrap());
+        assert_eq!(base, toml::from_str("b = 3\n[a]\nc = 4\n").unwrap());
+    }
+
+    #[test]
+    fn drop_ins_in_lexical_order() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in [
+            "20-late.toml",
+            "10-early.toml",
+            "30-ignored.conf",
+            "00-dir.toml.d",
+        ] {
+            fs::write(dir.path().join(name), "").unwrap();
+        }
+
+        let names: Vec<_> = drop_ins(dir.path())
+            .unwrap()
+            .iter()
+            .map(|path| path.file_name().unwrap().to_owned())
+            .collect();
+        assert_eq!(names, ["10-early.toml", "20-late.toml"]);
+        assert!(drop_ins(&dir.path().join("missing")).unwrap().is_empty());
+    }
+
+    #[test]
+    fn load_config_merges_drop_ins_in_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        let drop_ins = dir.path().join("config.d");
+        fs::create_dir(&drop_ins).unwrap();
+        fs::write(&path, "[ui]\nwidth = 40\ngreeting = \"Main\"\n").unwrap();
+        fs::write(drop_ins.join("20-late.toml"), "[ui]\ngreeting = \"Late\"\n").unwrap();
+        fs::write(
+            drop_ins.join("10-early.toml"),
+            "[ui]\ngreeting = \"Early\"\nwidth = 60\n",
+        )
+        .unwrap();
+        fs::write(drop_ins.join("30-ignored.conf"), "not toml").unwrap();
+
+        let config = load_config(Some(&path)).unwrap();
+        assert_eq!(config.ui.width, 60);
+        assert_eq!(config.ui.greeting.as_deref(), Some("Late"));
+
+        assert!(load_config(Some(&dir.path().join("missing.toml"))).is_err());
+    }
+}
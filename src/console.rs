@@ -0,0 +1,94 @@
+use ratatui::style::Color;
+use std::{
+    io::{self, Write},
+    panic,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+};
+
+// From <linux/kd.h>.
+const KDGKBTYPE: libc::Ioctl = 0x4B33;
+const KDGKBMODE: libc::Ioctl = 0x4B44;
+const K_UNICODE: libc::c_int = 0x03;
+const GIO_CMAP: libc::Ioctl = 0x4B70;
+const PIO_CMAP: libc::Ioctl = 0x4B71;
+
+/// Whether stdout is a Linux virtual terminal. Only the console answers
+/// KDGKBTYPE; terminal emulators, ptys and serial lines do not.
+pub fn is_vt() -> bool {
+    let mut kb_type: libc::c_char = 0;
+    // SAFETY: KDGKBTYPE writes one char to the pointer, which is valid for it.
+    unsafe { libc::ioctl(libc::STDOUT_FILENO, KDGKBTYPE, &mut kb_type) == 0 }
+}
+
+/// Whether the console is in UTF-8 mode, judged by its keyboard mode, which
+/// is set along with it; `None` if stdout is not a Linux virtual terminal.
+pub fn utf8_console() -> Option<bool> {
+    let mut mode: libc::c_int = 0;
+    // SAFETY: KDGKBMODE writes one int to the pointer, which is valid for it.
+    if unsafe { libc::ioctl(libc::STDOUT_FILENO, KDGKBMODE, &mut mode) } != 0 {
+        return None;
+    }
+    Some(mode == K_UNICODE)
+}
+
+/// The Linux console's 16-colour palette, replaced while the greeter runs. The
+/// original palette is restored when this is dropped, before the session starts,
+/// or on a panic, since release builds abort without unwinding.
+pub struct ConsolePalette {
+    saved: [u8; 48],
+    // Whether the panic hook still has a palette to restore. Hooks installed
+    // later chain to ours, so it is switched off rather than removed.
+    hooked: Arc<AtomicBool>,
+}
+
+impl ConsolePalette {
+    /// Sets the palette with the VT palette escape sequence (`ESC ] P n rrggbb`)
+    /// if stdout is a Linux virtual terminal. Entries that are not RGB colours
+    /// are left alone.
+    pub fn apply(palette: &[Color]) -> Option<Self> {
+        if !is_vt() {
+            return None;
+        }
+
+        let mut saved = [0u8; 48];
+        // SAFETY: GIO_CMAP writes 16 RGB triplets, which is what `saved` holds.
+        if unsafe { libc::ioctl(libc::STDOUT_FILENO, GIO_CMAP, saved.as_mut_ptr()) } != 0 {
+            return None;
+        }
+
+        let hooked = Arc::new(AtomicBool::new(true));
+        let hook = panic::take_hook();
+        let active = Arc::clone(&hooked);
+        panic::set_hook(Box::new(move |info| {
+            if active.load(Ordering::SeqCst) {
+                restore(&saved);
+            }
+            hook(info);
+        }));
+
+        let mut stdout = io::stdout().lock();
+        for (i, color) in palette.iter().enumerate().take(16) {
+            if let Color::Rgb(r, g, b) = color {
+                let _ = write!(stdout, "\x1b]P{i:x}{r:02x}{g:02x}{b:02x}");
+            }
+        }
+        let _ = stdout.flush();
+
+        Some(Self { saved, hooked })
+    }
+}
+
+impl Drop for ConsolePalette {
+    fn drop(&mut self) {
+        self.hooked.store(false, Ordering::SeqCst);
+        restore(&self.saved);
+    }
+}
+
+fn restore(saved: &[u8; 48]) {
+    // SAFETY: PIO_CMAP reads 16 RGB triplets, which is what `saved` holds.
+    unsafe { libc::ioctl(libc::STDOUT_FILENO, PIO_CMAP, saved.as_ptr()) };
+}
@@ -0,0 +1,463 @@
+use std::{collections::HashMap, env, fs, os::unix::fs::PermissionsExt, path::Path};
+
+/// Search path used for `TryExec` when `PATH` is unset.
+const DEFAULT_PATH: &str = "/usr/local/bin:/usr/bin:/bin";
+
+/// Replaces the escape sequences allowed in desktop-entry string values
+/// (`\s`, `\n`, `\t`, `\r` and `\\`).
+pub fn unescape_value(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    let mut chars = value.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('s') => out.push(' '),
+            Some('n') => out.push('\n'),
+            Some('t') => out.push('\t'),
+            Some('r') => out.push('\r'),
+            Some(other) => {
+                if other != '\\' {
+                    out.push('\\');
+                }
+                out.push(other);
+            }
+            None => out.push('\\'),
+        }
+    }
+
+    out
+}
+
+/// Splits an (already unescaped) `Exec` value into argv following the Desktop
+/// Entry spec: arguments are separated by spaces, double quotes group an argument
+/// and allow `\"`, `` \` ``, `\$` and `\\` inside, and field codes such as `%f`
+/// or `%U` are removed (`%%` becomes `%`).
+///
+/// Returns `None` for unterminated quotes or when no program is left.
+pub fn split_exec(exec: &str) -> Option<Vec<String>> {
+    split(exec, true)
+}
+
+/// Joins argv into an `Exec`-style command line that [`split_exec`] turns back
+/// into the same argv.
+pub fn join_exec(args: &[String]) -> String {
+    args.iter()
+        .map(|arg| quote_arg(arg))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn quote_arg(arg: &str) -> String {
+    let arg = arg.replace('%', "%%");
+    let needs_quotes = arg.is_empty()
+        || arg
+            .chars()
+            .any(|c| c.is_whitespace() || "\"'\\><~|&;$*?#()`".contains(c));
+    if !needs_quotes {
+        return arg;
+    }
+
+    let mut quoted = String::from('"');
+    for c in arg.chars() {
+        if matches!(c, '"' | '`' | '$' | '\\') {
+            quoted.push('\\');
+        }
+        quoted.push(c);
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Splits a plain command line with the same quoting rules as [`split_exec`],
+/// but keeps `%` characters as they are.
+pub fn split_command(line: &str) -> Option<Vec<String>> {
+    split(line, false)
+}
+
+fn split(exec: &str, field_codes: bool) -> Option<Vec<String>> {
+    let mut args = Vec::new();
+    let mut arg = String::new();
+    // Set once the current argument has content or quotes, so `""` stays an argument
+    // while a lone field code disappears entirely.
+    let mut in_arg = false;
+    let mut quoted = false;
+    let mut chars = exec.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => {
+                quoted = !quoted;
+                in_arg = true;
+            }
+            '\\' if quoted => {
+                let next = chars.next()?;
+                if !matches!(next, '"' | '`' | '$' | '\\') {
+                    arg.push('\\');
+                }
+                arg.push(next);
+            }
+            ' ' | '\t' | '\n' if !quoted => {
+                if in_arg {
+                    args.push(std::mem::take(&mut arg));
+                    in_arg = false;
+                }
+            }
+            '%' if field_codes => match chars.next() {
+                Some('%') | None => {
+                    arg.push('%');
+                    in_arg = true;
+                }
+                Some(_) => {}
+            },
+            _ => {
+                arg.push(c);
+                in_arg = true;
+            }
+        }
+    }
+
+    if quoted {
+        return None;
+    }
+    if in_arg {
+        args.push(arg);
+    }
+
+    (!args.is_empty()).then_some(args)
+}
+
+/// The `[Desktop Entry]` group of a desktop file. Other groups such as
+/// `[Desktop Action ...]` are ignored.
+#[derive(Debug, Default)]
+pub struct DesktopEntry {
+    entries: HashMap<String, String>,
+}
+
+impl DesktopEntry {
+    pub fn parse(contents: &str) -> Self {
+        let mut entries = HashMap::new();
+        let mut in_main_group = false;
+
+        for line in contents.lines() {
+            let line = line.trim_start();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            if let Some(group) = line.strip_prefix('[') {
+                in_main_group = group.trim_end() == "Desktop Entry]";
+                continue;
+            }
+
+            if !in_main_group {
+                continue;
+            }
+
+            if let Some((key, value)) = line.split_once('=') {
+                // Keys must not repeat within a group; the first one wins.
+                entries
+                    .entry(key.trim_end().to_string())
+                    .or_insert_with(|| value.trim_start().to_string());
+            }
+        }
+
+        Self { entries }
+    }
+
+    /// A string value with escape sequences resolved.
+    pub fn string(&self, key: &str) -> Option<String> {
+        self.entries.get(key).map(|value| unescape_value(value))
+    }
+
+    /// A localestring value: the best `key[locale]` translation for `locale`,
+    /// falling back to the untranslated `key`.
+    pub fn localized(&self, key: &str, locale: Option<&Locale>) -> Option<String> {
+        locale
+            .into_iter()
+            .flat_map(Locale::candidates)
+            .find_map(|suffix| self.string(&format!("{key}[{suffix}]")))
+            .or_else(|| self.string(key))
+    }
+
+    pub fn boolean(&self, key: &str) -> bool {
+        self.entries.get(key).is_some_and(|value| value == "true")
+    }
+
+    /// A `;`-separated list value; `\;` stands for a literal semicolon.
+    pub fn list(&self, key: &str) -> Vec<String> {
+        let Some(value) = self.entries.get(key) else {
+            return Vec::new();
+        };
+
+        let mut items = Vec::new();
+        let mut item = String::new();
+        let mut chars = value.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                // Escapes other than `\;` are left for `unescape_value`, but skipped
+                // here so that `\\;` ends the item after a backslash.
+                '\\' => match chars.next() {
+                    Some(';') => item.push(';'),
+                    Some(next) => {
+                        item.push('\\');
+                        item.push(next);
+                    }
+                    None => item.push('\\'),
+                },
+                ';' => items.push(std::mem::take(&mut item)),
+                _ => item.push(c),
+            }
+        }
+        items.push(item);
+
+        items
+            .iter()
+            .map(|item| unescape_value(item))
+            .filter(|item| !item.is_empty())
+            .collect()
+    }
+}
+
+/// The message locale, `lang_COUNTRY.ENCODING@MODIFIER` without the encoding.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Locale {
+    lang: String,
+    country: Option<String>,
+    modifier: Option<String>,
+}
+
+impl Locale {
+    /// Reads the locale used for messages from `LC_ALL`, `LC_MESSAGES` or `LANG`.
+    pub fn from_env() -> Option<Self> {
+        ["LC_ALL", "LC_MESSAGES", "LANG"]
+            .iter()
+            .filter_map(|var| env::var(var).ok())
+            .find(|value| !value.is_empty())
+            .and_then(|value| Self::parse(&value))
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        let (rest, modifier) = match value.split_once('@') {
+            Some((rest, modifier)) => (rest, Some(modifier.to_string())),
+            None => (value, None),
+        };
+        let rest = rest.split_once('.').map_or(rest, |(rest, _encoding)| rest);
+        let (lang, country) = match rest.split_once('_') {
+            Some((lang, country)) => (lang, Some(country.to_string())),
+            None => (rest, None),
+        };
+
+        if lang.is_empty() || lang == "C" || lang == "POSIX" {
+            return None;
+        }
+
+        Some(Self {
+            lang: lang.to_string(),
+            country,
+            modifier,
+        })
+    }
+
+    /// Locale keys to look up, most specific first, in the order the spec gives:
+    /// `lang_COUNTRY@MODIFIER`, `lang_COUNTRY`, `lang@MODIFIER`, `lang`.
+    fn candidates(&self) -> Vec<String> {
+        let lang = &self.lang;
+        let mut candidates = Vec::with_capacity(4);
+
+        if let (Some(country), Some(modifier)) = (&self.country, &self.modifier) {
+            candidates.push(format!("{lang}_{country}@{modifier}"));
+        }
+        if let Some(country) = &self.country {
+            candidates.push(format!("{lang}_{country}"));
+        }
+        if let Some(modifier) = &self.modifier {
+            candidates.push(format!("{lang}@{modifier}"));
+        }
+        candidates.push(lang.clone());
+
+        candidates
+    }
+}
+
+/// Checks a `TryExec` value: absolute paths must be executable files, bare
+/// names are looked up in `PATH`.
+pub fn try_exec(program: &str) -> bool {
+    if program.contains('/') {
+        return is_executable(Path::new(program));
+    }
+
+    let path = env::var("PATH").unwrap_or_else(|_| DEFAULT_PATH.to_string());
+    env::split_paths(&path).any(|dir| is_executable(&dir.join(program)))
+}
+
+fn is_executable(path: &Path) -> bool {
+    fs::metadata(path).is_ok_and(|meta| meta.is_file() && meta.permissions().mode() & 0o111 != 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Option<Vec<String>> {
+        Some(args.iter().map(|arg| arg.to_string()).collect())
+    }
+
+    #[test]
+    fn split_exec_quoting() {
+        assert_eq!(split_exec("sway"), args(&["sway"]));
+        assert_eq!(
+            split_exec("  env  A=1\tsway -d "),
+            args(&["env", "A=1", "sway", "-d"])
+        );
+        assert_eq!(
+            split_exec(r#"sh -c "exec sway --my-next-gpu-wont-be-nvidia""#),
+            args(&["sh", "-c", "exec sway --my-next-gpu-wont-be-nvidia"])
+        );
+        assert_eq!(split_exec(r#"app "" x"#), args(&["app", "", "x"]));
+        assert_eq!(split_exec(r#"app a"b c"d"#), args(&["app", "ab cd"]));
+        assert_eq!(split_exec(r#"app "unterminated"#), None);
+        assert_eq!(split_exec(""), None);
+        assert_eq!(split_exec("   "), None);
+    }
+
+    #[test]
+    fn split_exec_escapes() {
+        assert_eq!(
+            split_exec(r#"sh -c "echo \"\$HOME\" \`id\` \\""#),
+            args(&["sh", "-c", r#"echo "$HOME" `id` \"#])
+        );
+        // Other backslashes inside quotes are kept as they are.
+        assert_eq!(split_exec(r#"app "a\nb""#), args(&["app", r"a\nb"]));
+        // Outside quotes a backslash is an ordinary character.
+        assert_eq!(split_exec(r"app a\b"), args(&["app", r"a\b"]));
+        assert_eq!(split_exec(r#"app "trailing\"#), None);
+    }
+
+    #[test]
+    fn split_exec_field_codes() {
+        assert_eq!(split_exec("app %U"), args(&["app"]));
+        assert_eq!(split_exec("app %f --flag %i"), args(&["app", "--flag"]));
+        assert_eq!(split_exec("app 100%%"), args(&["app", "100%"]));
+        assert_eq!(split_exec(r#"app "%%u""#), args(&["app", "%u"]));
+        assert_eq!(split_exec("app --file=%f"), args(&["app", "--file="]));
+        assert_eq!(split_exec("app 50%"), args(&["app", "50%"]));
+        assert_eq!(split_exec("%U"), None);
+    }
+
+    #[test]
+    fn split_command_keeps_percent() {
+        assert_eq!(
+            split_command(r#"app %U "a b" 100%%"#),
+            args(&["app", "%U", "a b", "100%%"])
+        );
+    }
+
+    #[test]
+    fn join_exec_roundtrip() {
+        assert_eq!(join_exec(&args(&["sway", "-d"]).unwrap()), "sway -d");
+        assert_eq!(
+            join_exec(&args(&["sh", "-c", "echo $HOME"]).unwrap()),
+            r#"sh -c "echo \$HOME""#
+        );
+
+        for argv in [
+            &["sway"][..],
+            &["sh", "-c", "exec sway \"$@\" `id` \\ 'x'"],
+            &["app", "", "  ", "%U", "100%", "%%"],
+            &["env", "A=1;B=2", "x|y", "a\tb", "~/bin/app", "#c"],
+            &["app", "line\nbreak", "tab\there"],
+        ] {
+            let argv = args(argv).unwrap();
+            assert_eq!(split_exec(&join_exec(&argv)), Some(argv));
+        }
+    }
+
+    #[test]
+    fn unescape_values() {
+        assert_eq!(unescape_value(r"a\sb\nc\td\re"), "a b\nc\td\re");
+        assert_eq!(unescape_value(r"C:\\path"), r"C:\path");
+        assert_eq!(unescape_value(r"keep \; and \x"), r"keep \; and \x");
+        assert_eq!(unescape_value(r"trailing\"), r"trailing\");
+    }
+
+    #[test]
+    fn parse_reads_only_the_main_group() {
+        let entry = DesktopEntry::parse(
+            "# comment\n\
+             Name=Outside\n\
+             [Desktop Entry]\n\
+             Name = Sway\n\
+             Exec=sway\n\
+             Exec=ignored\n\
+             \n\
+             [Desktop Action new]\n\
+             Name=Action\n\
+             Icon=action\n\
+             [Desktop Entry]   \n\
+             Icon=sway\n",
+        );
+        assert_eq!(entry.string("Name").as_deref(), Some("Sway"));
+        assert_eq!(entry.string("Exec").as_deref(), Some("sway"));
+        assert_eq!(entry.string("Icon").as_deref(), Some("sway"));
+        assert_eq!(entry.string("Comment"), None);
+    }
+
+    #[test]
+    fn parse_booleans() {
+        let entry = DesktopEntry::parse("[Desktop Entry]\nHidden=true\nNoDisplay=false\n");
+        assert!(entry.boolean("Hidden"));
+        assert!(!entry.boolean("NoDisplay"));
+        assert!(!entry.boolean("Terminal"));
+        assert!(!DesktopEntry::parse("[Desktop Entry]\nHidden=True\n").boolean("Hidden"));
+    }
+
+    #[test]
+    fn parse_lists() {
+        let entry = DesktopEntry::parse(
+            "[Desktop Entry]\n\
+             DesktopNames=sway;wlroots;\n\
+             Keywords=a\\;b;c\\sd;;x\\\\;\\\\\\;\n\
+             Empty=\n",
+        );
+        assert_eq!(entry.list("DesktopNames"), ["sway", "wlroots"]);
+        assert_eq!(entry.list("Keywords"), ["a;b", "c d", "x\\", "\\;"]);
+        assert!(entry.list("Empty").is_empty());
+        assert!(entry.list("Missing").is_empty());
+    }
+
+    #[test]
+    fn locale_candidates() {
+        let candidates = |value| Locale::parse(value).unwrap().candidates();
+        assert_eq!(
+            candidates("sr_RS.UTF-8@latin"),
+            ["sr_RS@latin", "sr_RS", "sr@latin", "sr"]
+        );
+        assert_eq!(candidates("de_DE.UTF-8"), ["de_DE", "de"]);
+        assert_eq!(candidates("ca@valencia"), ["ca@valencia", "ca"]);
+        assert_eq!(candidates("fr"), ["fr"]);
+        assert_eq!(Locale::parse("C.UTF-8"), None);
+        assert_eq!(Locale::parse("POSIX"), None);
+        assert_eq!(Locale::parse(""), None);
+    }
+
+    #[test]
+    fn localized_prefers_the_most_specific_key() {
+        let entry = DesktopEntry::parse(
+            "[Desktop Entry]\n\
+             Name=Session\n\
+             Name[sr]=Sesija sr\n\
+             Name[sr@latin]=Sesija latin\n\
+             Name[sr_RS]=Sesija RS\n",
+        );
+        let name = |value| entry.localized("Name", Locale::parse(value).as_ref());
+        assert_eq!(name("sr_RS.UTF-8@latin").as_deref(), Some("Sesija RS"));
+        assert_eq!(name("sr_ME@latin").as_deref(), Some("Sesija latin"));
+        assert_eq!(name("sr_ME").as_deref(), Some("Sesija sr"));
+        assert_eq!(name("de_DE").as_deref(), Some("Session"));
+        assert_eq!(name("C").as_deref(), Some("Session"));
+    }
+}
@@ -1,50 +1,83 @@
+use crate::app::{AppState, AuthState};
+use crate::auth::{AuthError, AuthEvent, AuthWorker, PamMessage};
+use crate::config::Config;
+use crate::log::log;
 use crate::power::{PowerAction, power};
-use crate::state::{FerriteState, save_state};
+use crate::state::{state_path, update_state};
 use crate::widgets::widget::InputField;
-use crate::{app::AppState, auth::authenticate};
 use ratatui::crossterm::event::{Event, KeyCode};
 
+/// How long a save failure stays on screen before the session starts, in ticks.
+const NOTICE_TICKS: usize = 30;
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Action {
     Continue,
     Quit,
 }
-pub fn handle_event(event: &Event, app_state: &mut AppState) -> Result<Action, color_eyre::Report> {
+pub fn handle_event(
+    event: &Event,
+    app_state: &mut AppState,
+    config: &Config,
+) -> Result<Action, color_eyre::Report> {
+    // The session has already started; the greeter only stays up to show why
+    // the login will not be remembered.
+    if app_state.quit_at.is_some() {
+        return Ok(Action::Continue);
+    }
+
     if let Event::Key(key) = event {
+        if app_state.editor.is_some() {
+            return Ok(handle_editor_event(event, app_state, config));
+        }
+
+        let in_conversation = app_state.auth_worker.is_some();
+        let busy = matches!(app_state.auth_state, AuthState::Authenticating);
+
+        let keys = &config.keys;
         match key.code {
-            KeyCode::Esc => return Ok(Action::Quit),
-            KeyCode::Down => app_state.focus_next(),
-            KeyCode::Up => app_state.focus_prev(),
-            KeyCode::Enter => {
-                app_state.auth_state = crate::app::AuthState::Authenticating;
-                let res = authenticate(
-                    &app_state.username.get_value(),
-                    &app_state.password.get_value(),
-                    &[app_state.session.get_value().exec],
-                );
-
-                match res {
-                    Ok(_) => {
-                        let state = FerriteState {
-                            last_user: Some(app_state.username.get_value()),
-                            last_session: Some(app_state.session.get_value().name),
-                            version: 1,
-                        };
-                        let _=save_state(&state) ;// handle later
-                        return Ok(Action::Quit);
-                    }
-                    Err(err) => {
-                        app_state.auth_state = crate::app::AuthState::Failed(err);
-                    }
-                }
+            _ if keys.cancel.matches(key) && in_conversation => {
+                app_state.end_conversation(true);
+                app_state.auth_state = AuthState::None;
+            }
+            _ if keys.cancel.matches(key) => return Ok(Action::Quit),
+            _ if keys.shutdown.matches(key) => power(PowerAction::Shutdown, &config.power),
+            _ if keys.reboot.matches(key) => power(PowerAction::Reboot, &config.power),
+            _ if busy => {}
+            KeyCode::Down if !in_conversation => app_state.focus_next(),
+            KeyCode::Up if !in_conversation => app_state.focus_prev(),
+            KeyCode::Enter => submit(app_state, config),
+            _ if keys.edit.matches(key)
+                && !in_conversation
+                && app_state.focus_index == app_state.session.index =>
+            {
+                app_state.open_editor();
             }
-            KeyCode::F(1) => power(PowerAction::Shutdown),
-            KeyCode::F(2) => power(PowerAction::Reboot),
 
+            _ if in_conversation => {
+                app_state
+                    .password
+                    .handle_event(app_state.focus_index, event);
+            }
+            // The custom command input takes the keys, except Left at its start,
+            // which goes back to the previous session entry.
+            _ if app_state.focus_index == app_state.session.index
+                && app_state.custom_selected() =>
+            {
+                if key.code == KeyCode::Left && app_state.command.input.cursor() == 0 {
+                    app_state.session.handle_event(app_state.focus_index, event);
+                } else {
+                    app_state.command.handle_event(app_state.focus_index, event);
+                }
+            }
             _ => {
+                let selected_user = app_state.username.selected_idx;
                 app_state
                     .username
                     .handle_event(app_state.focus_index, event);
+                if app_state.username.selected_idx != selected_user {
+                    app_state.select_user_session();
+                }
 
                 app_state
                     .password
@@ -56,3 +89,120 @@ pub fn handle_event(event: &Event, app_state: &mut AppState) -> Result<Action, c
     }
     Ok(Action::Continue)
 }
+
+/// Starts a login with the form's values, or answers the pending PAM prompt.
+fn submit(app_state: &mut AppState, config: &Config) {
+    let answer = app_state.password.get_value();
+    app_state.password.input.reset();
+
+    match &app_state.auth_worker {
+        Some(worker) => worker.respond(Some(answer)),
+        None => {
+            let Some(username) = app_state.username.get_value() else {
+                app_state.auth_state = AuthState::Failed(AuthError::NoUser);
+                return;
+            };
+            let Some(session) = app_state.selected_session() else {
+                app_state.auth_state =
+                    AuthState::Failed(AuthError::InvalidSession("enter a command to run".into()));
+                return;
+            };
+
+            let (cmd, env) = match session.launch(&username, config) {
+                Ok(launch) => launch,
+                Err(e) => {
+                    app_state.auth_state =
+                        AuthState::Failed(AuthError::InvalidSession(format!("{e:#}")));
+                    return;
+                }
+            };
+
+            let worker = AuthWorker::spawn(
+                username,
+                (!answer.is_empty()).then_some(answer),
+                cmd,
+                env,
+                config.greetd.socket.as_deref(),
+            );
+            match worker {
+                Ok(worker) => app_state.auth_worker = Some(worker),
+                Err(err) => {
+                    app_state.auth_state = AuthState::Failed(err);
+                    return;
+                }
+            }
+        }
+    }
+    app_state.auth_state = AuthState::Authenticating;
+}
+
+/// Keys for the command editor overlay: Enter launches the edited command for
+/// this login, the cancel key closes the editor without changes.
+fn handle_editor_event(event: &Event, app_state: &mut AppState, config: &Config) -> Action {
+    let Event::Key(key) = event else {
+        return Action::Continue;
+    };
+
+    match key.code {
+        _ if config.keys.cancel.matches(key) => app_state.editor = None,
+        KeyCode::Enter => {
+            if app_state.close_editor() {
+                submit(app_state, config);
+            }
+        }
+        _ => {
+            if let Some(editor) = &mut app_state.editor {
+                editor.handle_event(editor.index, event);
+            }
+        }
+    }
+    Action::Continue
+}
+
+pub fn handle_auth_event(
+    event: AuthEvent,
+    app_state: &mut AppState,
+    config: &Config,
+) -> Result<Action, color_eyre::Report> {
+    match event {
+        AuthEvent::Message(message) => app_state.pam_message = Some(message),
+        AuthEvent::PretypedUnused => {
+            app_state.pam_message = Some(PamMessage {
+                text: app_state.labels.pretyped_unused.clone(),
+                error: true,
+            })
+        }
+        AuthEvent::Prompt { message, secret } => {
+            app_state.show_prompt(&message, secret);
+            app_state.auth_state = AuthState::None;
+        }
+        AuthEvent::Started => {
+            app_state.end_conversation(false);
+            let login = app_state
+                .username
+                .get_value()
+                .zip(app_state.selected_session());
+            let saved = update_state(&state_path(&config.state), |state| {
+                if let Some((username, session)) = &login {
+                    state.record_login(username, session);
+                }
+            });
+
+            // The session starts once the greeter exits, so stay up briefly to
+            // show why the choice will not be remembered.
+            if let Err(e) = saved {
+                let notice = format!("Could not save state: {e:#}");
+                log(&notice);
+                app_state.state_notice = Some(notice);
+                app_state.quit_at = Some(app_state.tick.wrapping_add(NOTICE_TICKS));
+                return Ok(Action::Continue);
+            }
+            return Ok(Action::Quit);
+        }
+        AuthEvent::Failed(err) => {
+            app_state.end_conversation(false);
+            app_state.auth_state = AuthState::Failed(err);
+        }
+    }
+    Ok(Action::Continue)
+}
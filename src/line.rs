@@ -0,0 +1,222 @@
+use color_eyre::Result;
+use std::{
+    env,
+    io::{self, BufRead, Write},
+};
+
+use crate::auth::{AuthError, AuthSession, AuthStep};
+use crate::config::Config;
+use crate::log::log;
+use crate::state::{FerriteState, state_path, update_state};
+use crate::util::{Session, fallback_session};
+
+/// Whether the terminal is too limited for the full-screen UI: no `TERM`, a dumb
+/// terminal, or a VT100-style serial terminal without an alternate screen.
+pub fn limited_terminal() -> bool {
+    let term = env::var("TERM").unwrap_or_default();
+    term.is_empty() || term == "dumb" || term == "unknown" || term.starts_with("vt")
+}
+
+/// A plain greeter reading one line at a time, in the spirit of agreety: asks for
+/// the username, answers greetd's prompts and then offers the sessions by number.
+/// Returns once a session is started, or at the end of input.
+pub fn run(
+    sessions: Vec<Session>,
+    hostname: &str,
+    state: &FerriteState,
+    config: &Config,
+) -> Result<()> {
+    let mut sessions = if sessions.is_empty() {
+        vec![fallback_session(config)]
+    } else {
+        sessions
+    };
+    sessions.extend(
+        state
+            .recent_commands
+            .iter()
+            .filter_map(|line| Session::custom(line)),
+    );
+
+    let default_user = config
+        .defaults
+        .user
+        .as_deref()
+        .or(state.last_user.as_deref());
+    let prompt = match default_user {
+        Some(user) => format!("{hostname} login [{user}]: "),
+        None => format!("{hostname} login: "),
+    };
+
+    let mut input = io::stdin().lock();
+    loop {
+        println!();
+        let Some(line) = read_line(&mut input, &prompt, false)? else {
+            return Ok(());
+        };
+        let username = match (line.trim(), default_user) {
+            ("", Some(user)) => user.to_string(),
+            ("", None) => continue,
+            (name, _) => name.to_string(),
+        };
+
+        match login(&mut input, &username, &sessions, state, config) {
+            Ok(Some(session)) => {
+                remember(&username, &session, config);
+                return Ok(());
+            }
+            Ok(None) => return Ok(()),
+            Err(e) => match e.downcast_ref::<AuthError>() {
+                Some(AuthError::AuthFailed(_) | AuthError::InvalidSession(_)) => println!("{e}"),
+                _ => return Err(e),
+            },
+        }
+    }
+}
+
+/// Authenticates `username` and starts the session they choose. Returns the
+/// session, or `None` at the end of input.
+fn login(
+    input: &mut impl BufRead,
+    username: &str,
+    sessions: &[Session],
+    state: &FerriteState,
+    config: &Config,
+) -> Result<Option<Session>> {
+    let mut auth = AuthSession::connect(config.greetd.socket.as_deref())?;
+    let mut step = auth.create(username)?;
+
+    loop {
+        step = match step {
+            AuthStep::Message(message) => {
+                println!("{}", message.text);
+                auth.respond(None)?
+            }
+            AuthStep::Prompt { message, secret } => {
+                let Some(answer) = read_line(input, &message, secret)? else {
+                    auth.cancel();
+                    return Ok(None);
+                };
+                auth.respond(Some(answer))?
+            }
+            AuthStep::Authenticated => break,
+        };
+    }
+
+    loop {
+        let Some(session) = choose_session(input, username, sessions, state, config)? else {
+            auth.cancel();
+            return Ok(None);
+        };
+        match session.launch(username, config) {
+            Ok((cmd, env)) => {
+                auth.start(&cmd, &env)?;
+                return Ok(Some(session));
+            }
+            Err(e) => println!("{e:#}"),
+        }
+    }
+}
+
+/// Lists the sessions by number and reads the choice: a number, nothing for the
+/// preselected or remembered session, or a command line to run.
+fn choose_session(
+    input: &mut impl BufRead,
+    username: &str,
+    sessions: &[Session],
+    state: &FerriteState,
+    config: &Config,
+) -> io::Result<Option<Session>> {
+    let preselected = config.defaults.session.as_ref().and_then(|wanted| {
+        sessions
+            .iter()
+            .position(|s| &s.name == wanted || &s.id == wanted)
+    });
+    let remembered = state
+        .user(username)
+        .and_then(|user| user.session_position(sessions));
+    let default = preselected.or(remembered).unwrap_or(0);
+
+    println!();
+    for (i, session) in sessions.iter().enumerate() {
+        println!("{:>3}) {}", i + 1, session.label());
+    }
+    let prompt = format!(
+        "{} [{}], or a command: ",
+        config.labels.session,
+        default + 1
+    );
+
+    loop {
+        let Some(line) = read_line(input, &prompt, false)? else {
+            return Ok(None);
+        };
+        let choice = line.trim();
+        if choice.is_empty() {
+            return Ok(Some(sessions[default].clone()));
+        }
+        match choice.parse::<usize>() {
+            Ok(n) if (1..=sessions.len()).contains(&n) => {
+                return Ok(Some(sessions[n - 1].clone()));
+            }
+            Ok(_) => println!("There is no session {choice}"),
+            Err(_) => match Session::custom(choice) {
+                Some(session) => return Ok(Some(session)),
+                None => println!("Cannot parse the command: {choice}"),
+            },
+        }
+    }
+}
+
+fn remember(username: &str, session: &Session, config: &Config) {
+    let saved = update_state(&state_path(&config.state), |state| {
+        state.record_login(username, session)
+    });
+    if let Err(e) = saved {
+        let notice = format!("Could not save state: {e:#}");
+        log(&notice);
+        println!("{notice}");
+    }
+}
+
+/// Prints `prompt` and reads a line, with echo turned off for `secret` answers.
+/// Returns `None` at the end of input.
+fn read_line(input: &mut impl BufRead, prompt: &str, secret: bool) -> io::Result<Option<String>> {
+    print!("{prompt}");
+    io::stdout().flush()?;
+
+    let _echo = secret.then(EchoOff::new).flatten();
+    let mut line = String::new();
+    if input.read_line(&mut line)? == 0 {
+        println!();
+        return Ok(None);
+    }
+    Ok(Some(line.trim_end_matches(['\r', '\n']).to_string()))
+}
+
+/// Turns off the terminal's echo, except for the newline, until dropped.
+struct EchoOff {
+    saved: libc::termios,
+}
+
+impl EchoOff {
+    fn new() -> Option<Self> {
+        let mut saved: libc::termios = unsafe { std::mem::zeroed() };
+        if unsafe { libc::tcgetattr(libc::STDIN_FILENO, &mut saved) } != 0 {
+            return None;
+        }
+
+        let mut quiet = saved;
+        quiet.c_lflag = (quiet.c_lflag & !libc::ECHO) | libc::ECHONL;
+        if unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &quiet) } != 0 {
+            return None;
+        }
+        Some(Self { saved })
+    }
+}
+
+impl Drop for EchoOff {
+    fn drop(&mut self) {
+        unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &self.saved) };
+    }
+}
@@ -0,0 +1,29 @@
+use std::{
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// The terminal belongs to the UI, so problems are appended here instead.
+pub const LOG_PATH: &str = "/var/log/ferrite/ferrite.log";
+
+/// Appends `message` to the log file, prefixed with the Unix time. Failures to
+/// write the log are ignored.
+pub fn log(message: &str) {
+    let _ = append(Path::new(LOG_PATH), message);
+}
+
+fn append(path: &Path, message: &str) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    writeln!(file, "{secs} {message}")
+}
@@ -1,52 +1,151 @@
 mod app;
 mod auth;
+mod check;
+mod cli;
+mod config;
+mod console;
+mod desktop;
 mod event;
+mod line;
+mod log;
 mod power;
 mod state;
+mod theme;
 mod ui;
 mod util;
 mod widgets;
+mod x11;
 
 use crate::app::AppState;
-use crate::event::{Action, handle_event};
-use crate::state::load_state;
+use crate::cli::{Cli, Command};
+use crate::config::{Config, load_config};
+use crate::console::{ConsolePalette, is_vt, utf8_console};
+use crate::event::{Action, handle_auth_event, handle_event};
+use crate::log::log;
+use crate::state::{load_state, state_path};
+use crate::theme::{ColorDepth, load_theme, utf8_locale};
 use crate::ui::render;
 use crate::util::{get_login_users, read_sessions};
+use clap::Parser;
 use color_eyre::Result;
 use ratatui::DefaultTerminal;
-use ratatui::crossterm::{
-    execute,
-    terminal::EnterAlternateScreen,
-};
+use ratatui::crossterm::{execute, terminal::EnterAlternateScreen};
+use std::time::Duration;
+
+/// How often the screen is redrawn while waiting for input or greetd.
+const TICK_RATE: Duration = Duration::from_millis(100);
 
 fn main() -> Result<()> {
     color_eyre::install()?;
 
-    let sessions = read_sessions()?;
+    let cli = Cli::parse();
+    if let Some(Command::Check { greeter_user }) = &cli.command {
+        std::process::exit(check::run(&cli, greeter_user));
+    }
+
+    // A broken config must not lock everyone out, so fall back to the defaults.
+    let mut config = load_config(cli.config.as_deref()).unwrap_or_else(|e| {
+        eprintln!("ferrite: ignoring config: {e:#}");
+        log(&format!("ignoring config: {e:#}"));
+        Config::default()
+    });
+    // A bad `--theme` only costs the theme.
+    if let Some(theme) = &cli.theme {
+        match load_theme(theme) {
+            Ok(theme) => config.theme = theme,
+            Err(e) => {
+                eprintln!("ferrite: ignoring theme: {e:#}");
+                log(&format!("ignoring theme: {e:#}"));
+            }
+        }
+    }
+    cli.apply(&mut config);
+
+    // greetd runs `ferrite x11-session <client...>` for natively launched X11 sessions.
+    if let Some(Command::X11Session { client }) = &cli.command {
+        let code = x11::run_session(client, &config.x11).unwrap_or_else(|e| {
+            eprintln!("ferrite: {e:#}");
+            1
+        });
+        std::process::exit(code);
+    }
+
+    // Serial consoles and minimal fonts have no box-drawing glyphs. greetd often
+    // starts the greeter without a locale; then a console tells by its mode, and
+    // terminal emulators are taken to be UTF-8.
+    let utf8 = utf8_locale().or_else(utf8_console).unwrap_or(true);
+    if config.ui.ascii.unwrap_or(!utf8) {
+        config.theme.ascii = true;
+        config.labels = config.labels.to_ascii();
+    }
+
+    let sessions = read_sessions(&config)?;
     let users = get_login_users()?;
-    let state = load_state();
+    let (state, state_notice) = load_state(&state_path(&config.state));
     let hostname = hostname::get()?.to_string_lossy().to_string();
-    let mut app_state = AppState::new(sessions, users, hostname, state);
+
+    // Serial lines and dumb terminals cannot show the full-screen UI.
+    if config.ui.line_mode.unwrap_or_else(line::limited_terminal) {
+        if let Some(notice) = &state_notice {
+            println!("{notice}");
+        }
+        return line::run(sessions, &hostname, &state, &config);
+    }
+
+    let mut app_state = AppState::new(sessions, users, hostname, state, &config);
+    app_state.state_notice = state_notice;
+
+    // On a bare console, draw with the theme's own palette until the session starts.
+    let palette = config
+        .theme
+        .palette
+        .as_deref()
+        .filter(|_| config.ui.console_palette)
+        .and_then(ConsolePalette::apply);
+    if palette.is_some()
+        && let Some(colors) = &config.theme.palette
+    {
+        config.theme = config.theme.on_palette(colors);
+    }
+    let depth = config
+        .ui
+        .colors
+        .unwrap_or_else(|| ColorDepth::detect(is_vt()));
+    config.theme = config.theme.downgrade(depth);
 
     // Explicitly enter alternate screen mode for fullscreen
     let mut stdout = std::io::stdout();
     execute!(stdout, EnterAlternateScreen)?;
-    
+
     let terminal = ratatui::init();
-    let result = run(terminal, &mut app_state);
-    
+    let result = run(terminal, &mut app_state, &config);
+
     ratatui::restore();
+    drop(palette);
     result
 }
 
-fn run(mut terminal: DefaultTerminal, app_state: &mut AppState) -> Result<()> {
+fn run(mut terminal: DefaultTerminal, app_state: &mut AppState, config: &Config) -> Result<()> {
     loop {
-        terminal.draw(|f| render(f, app_state))?;
-        let evt = ratatui::crossterm::event::read()?;
+        terminal.draw(|f| render(f, app_state, config))?;
 
-        match handle_event(&evt, app_state)? {
-            Action::Quit => return Ok(()),
-            Action::Continue => continue,
+        if ratatui::crossterm::event::poll(TICK_RATE)? {
+            let evt = ratatui::crossterm::event::read()?;
+            if handle_event(&evt, app_state, config)? == Action::Quit {
+                return Ok(());
+            }
         }
+
+        while let Some(evt) = app_state.auth_worker.as_ref().and_then(|w| w.try_recv()) {
+            if handle_auth_event(evt, app_state, config)? == Action::Quit {
+                return Ok(());
+            }
+        }
+
+        if app_state.quit_at == Some(app_state.tick) {
+            return Ok(());
+        }
+
+        app_state.tick = app_state.tick.wrapping_add(1);
     }
 }
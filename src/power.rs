@@ -1,13 +1,17 @@
+use crate::config::PowerConfig;
+
 pub enum PowerAction {
     Reboot,
     Shutdown,
 }
 
-pub fn power(action: PowerAction) {
+pub fn power(action: PowerAction, config: &PowerConfig) {
     let cmd = match action {
-        PowerAction::Reboot => "reboot",
-        PowerAction::Shutdown => "poweroff",
+        PowerAction::Reboot => &config.reboot,
+        PowerAction::Shutdown => &config.shutdown,
     };
 
-    let _ = std::process::Command::new("systemctl").arg(cmd).spawn();
+    if let Some((program, args)) = cmd.split_first() {
+        let _ = std::process::Command::new(program).args(args).spawn();
+    }
 }
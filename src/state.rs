@@ -1,41 +1,521 @@
-use anyhow::Ok;
+use anyhow::{Context, Ok, bail};
 use serde::{Deserialize, Serialize};
-use std::{fs, io::Write, path::PathBuf};
+use serde_json::{Map, Value};
+use std::{
+    collections::BTreeMap,
+    env,
+    ffi::OsStr,
+    fs::{self, File},
+    io::{ErrorKind, Write},
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
 use tempfile::NamedTempFile;
 
-#[derive(Serialize, Deserialize, Default, Debug)]
+use crate::config::StateConfig;
+use crate::log::log;
+use crate::util::{Session, SessionKind};
+
+/// Overrides the configured state file; `--state-file` overrides this in turn.
+pub const STATE_FILE_ENV: &str = "FERRITE_STATE_FILE";
+
+/// How many custom commands are remembered across boots.
+const MAX_RECENT_COMMANDS: usize = 5;
+
+/// Version written by this build.
+const STATE_VERSION: u8 = 2;
+
+type Migration = fn(&mut Map<String, Value>) -> anyhow::Result<()>;
+
+/// `MIGRATIONS[n]` upgrades a version `n` state file to version `n + 1`.
+const MIGRATIONS: [Migration; STATE_VERSION as usize] = [migrate_v0, migrate_v1];
+
+#[derive(Serialize, Deserialize, Debug)]
 pub struct FerriteState {
     pub version: u8,
     pub last_user: Option<String>,
-    pub last_session: Option<String>,
+    /// What each user chose last time, keyed by username.
+    #[serde(default)]
+    pub users: BTreeMap<String, UserState>,
+    /// Custom commands used to log in, most recent first.
+    #[serde(default)]
+    pub recent_commands: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug, Clone)]
+pub struct UserState {
+    /// The session entry the user logged in with.
+    pub last_session: Option<SessionRef>,
+    /// Display name of the session, as kept by version 1 state files. Only
+    /// used to find the session again until the user next logs in.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_session_name: Option<String>,
+    /// The custom command line the user last logged in with.
+    pub last_command: Option<String>,
+    /// Time of the last login, in seconds since the Unix epoch.
+    pub last_login: Option<u64>,
+}
+
+/// Identifies a session entry across boots and locales. Display names are
+/// translated and not unique, so entries are known by desktop-file ID and kind;
+/// a custom command's ID is its command line.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct SessionRef {
+    pub id: String,
+    pub kind: SessionKind,
+}
+
+impl SessionRef {
+    pub fn matches(&self, session: &Session) -> bool {
+        self.id == session.id && self.kind == session.kind
+    }
+}
+
+impl UserState {
+    /// Position of the remembered session in `sessions`: by ID and kind, or by
+    /// display name for a session remembered by version 1.
+    pub fn session_position(&self, sessions: &[Session]) -> Option<usize> {
+        let by_ref = self
+            .last_session
+            .as_ref()
+            .and_then(|last| sessions.iter().position(|s| last.matches(s)));
+        by_ref.or_else(|| {
+            let name = self.last_session_name.as_ref()?;
+            sessions.iter().position(|s| &s.name == name)
+        })
+    }
 }
 
-fn state_path() -> PathBuf {
-    PathBuf::from("/var/lib/ferrite/state.json")
+impl Default for FerriteState {
+    fn default() -> Self {
+        Self {
+            version: STATE_VERSION,
+            last_user: None,
+            users: BTreeMap::new(),
+            recent_commands: Vec::new(),
+        }
+    }
 }
 
-pub fn load_state() -> FerriteState {
-    let path = state_path();
+impl FerriteState {
+    pub fn user(&self, username: &str) -> Option<&UserState> {
+        self.users.get(username)
+    }
+
+    /// Records a successful login of `username` into `session`.
+    pub fn record_login(&mut self, username: &str, session: &Session) {
+        self.last_user = Some(username.to_string());
+
+        let user = self.users.entry(username.to_string()).or_default();
+        user.last_session = Some(SessionRef {
+            id: session.id.clone(),
+            kind: session.kind,
+        });
+        user.last_session_name = None;
+        user.last_login = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .ok()
+            .map(|d| d.as_secs());
+        if session.kind == SessionKind::Custom {
+            user.last_command = Some(session.id.clone());
+            self.remember_command(&session.id);
+        }
+    }
+
+    fn remember_command(&mut self, command: &str) {
+        self.recent_commands.retain(|c| c != command);
+        self.recent_commands.insert(0, command.to_string());
+        self.recent_commands.truncate(MAX_RECENT_COMMANDS);
+    }
+}
 
-    fs::read_to_string(path)
+/// The state file for this greeter: the configured path, or the seat's own file
+/// next to it when `per_seat` is set and `XDG_SEAT` names a seat.
+pub fn state_path(config: &StateConfig) -> PathBuf {
+    let seat = env::var("XDG_SEAT")
         .ok()
-        .and_then(|s| serde_json::from_str(&s).ok())
-        .unwrap_or_default()
+        .filter(|seat| !seat.is_empty() && !seat.contains('/'));
+
+    match seat {
+        Some(seat) if config.per_seat => {
+            let mut name = config.path.file_stem().unwrap_or_default().to_owned();
+            name.push(format!("-{seat}"));
+            if let Some(ext) = config.path.extension() {
+                name.push(".");
+                name.push(ext);
+            }
+            config.path.with_file_name(name)
+        }
+        _ => config.path.clone(),
+    }
 }
 
-pub fn save_state(state: &FerriteState) -> anyhow::Result<()> {
-    let path = state_path();
+/// `path` with `suffix` appended to its file name.
+fn with_suffix(path: &Path, suffix: impl AsRef<OsStr>) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(suffix);
+    PathBuf::from(name)
+}
 
+/// The lock file guarding the state file at `path`.
+pub fn lock_path(path: &Path) -> PathBuf {
+    with_suffix(path, ".lock")
+}
+
+/// Takes an exclusive advisory lock on `<path>.lock`, held until the returned
+/// file is dropped. The state file itself is replaced on save, so it cannot
+/// carry the lock.
+fn lock(path: &Path) -> anyhow::Result<File> {
     if let Some(dir) = path.parent() {
         fs::create_dir_all(dir)?
     }
 
+    let lock_path = lock_path(path);
+    let file = File::options()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(&lock_path)
+        .with_context(|| format!("opening {}", lock_path.display()))?;
+    file.lock()
+        .with_context(|| format!("locking {}", lock_path.display()))?;
+    Ok(file)
+}
+
+/// Version 0 is an unsaved default state, laid out like version 1.
+fn migrate_v0(_state: &mut Map<String, Value>) -> anyhow::Result<()> {
+    Ok(())
+}
+
+/// Version 1 kept one global `last_session`, a display name; it becomes the
+/// last user's, to be matched by name until they log in again.
+fn migrate_v1(state: &mut Map<String, Value>) -> anyhow::Result<()> {
+    let session = state.remove("last_session").unwrap_or(Value::Null);
+    let Some(user) = state.get("last_user").and_then(Value::as_str) else {
+        return Ok(());
+    };
+    if !session.is_string() {
+        return Ok(());
+    }
+
+    let mut user_state = Map::new();
+    user_state.insert(String::from("last_session_name"), session);
+    let mut users = Map::new();
+    users.insert(user.to_string(), Value::Object(user_state));
+    state.insert(String::from("users"), Value::Object(users));
+    Ok(())
+}
+
+/// Parses a state file of any known version, migrating it to the current one.
+pub fn parse_state(contents: &str) -> anyhow::Result<FerriteState> {
+    let value: Value = serde_json::from_str(contents)?;
+    let Value::Object(mut state) = value else {
+        bail!("not a JSON object");
+    };
+
+    let version = match state.get("version") {
+        None => 0,
+        Some(version) => version.as_u64().context("version is not a number")?,
+    };
+    if version > u64::from(STATE_VERSION) {
+        bail!("written by a newer ferrite (version {version})");
+    }
+
+    for migrate in &MIGRATIONS[version as usize..] {
+        migrate(&mut state)?;
+    }
+    state.insert(String::from("version"), STATE_VERSION.into());
+
+    Ok(serde_json::from_value(Value::Object(state))?)
+}
+
+/// Moves an unreadable state file aside, next to it, without replacing an
+/// earlier backup.
+fn back_up(path: &Path) -> anyhow::Result<PathBuf> {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+
+    let mut backup = with_suffix(path, format!(".corrupt-{secs}"));
+    for n in 1.. {
+        if !backup.exists() {
+            break;
+        }
+        backup = with_suffix(path, format!(".corrupt-{secs}.{n}"));
+    }
+
+    fs::rename(path, &backup)?;
+    Ok(backup)
+}
+
+/// Loads the saved state. Problems with the file are logged and returned as a
+/// notice for the user; an unreadable file is backed up and a fresh state used.
+pub fn load_state(path: &Path) -> (FerriteState, Option<String>) {
+    // Reading works without the lock too, since saves replace the file atomically.
+    let _lock = lock(path).inspect_err(|e| log(&format!("{e:#}")));
+    read_state(path).unwrap_or_else(|e| {
+        let notice = format!("{e:#}");
+        log(&notice);
+        (FerriteState::default(), Some(notice))
+    })
+}
+
+/// Reads the state file; a missing or corrupt file gives a fresh state. Fails
+/// if the file exists but cannot be read, so that it is not replaced.
+fn read_state(path: &Path) -> anyhow::Result<(FerriteState, Option<String>)> {
+    let contents = match fs::read_to_string(path) {
+        Result::Ok(contents) => contents,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok((FerriteState::default(), None)),
+        Err(e) => bail!("Cannot read {}: {e}", path.display()),
+    };
+
+    Ok(match parse_state(&contents) {
+        Result::Ok(state) => (state, None),
+        Err(e) => {
+            let notice = match back_up(path) {
+                Result::Ok(backup) => {
+                    format!(
+                        "Ignoring corrupt state ({e:#}), moved to {}",
+                        backup.display()
+                    )
+                }
+                Err(backup_err) => {
+                    format!("Ignoring corrupt state ({e:#}), backup failed: {backup_err:#}")
+                }
+            };
+            log(&notice);
+            (FerriteState::default(), Some(notice))
+        }
+    })
+}
+
+/// Applies `update` to the state on disk and saves it, holding the lock
+/// throughout so that greeters on other seats sharing the file do not lose each
+/// other's changes. Returns the saved state.
+pub fn update_state(
+    path: &Path,
+    update: impl FnOnce(&mut FerriteState),
+) -> anyhow::Result<FerriteState> {
+    let _lock = lock(path)?;
+
+    let (mut state, _) = read_state(path)?;
+    update(&mut state);
+    save_state(path, &state)?;
+
+    Ok(state)
+}
+
+fn save_state(path: &Path, state: &FerriteState) -> anyhow::Result<()> {
+    let dir = path.parent().unwrap_or(Path::new("."));
     let json = serde_json::to_string(state)?;
 
-    let mut tmp = NamedTempFile::new_in(path.parent().unwrap())?;
+    let mut tmp = NamedTempFile::new_in(dir)?;
     tmp.write_all(json.as_bytes())?;
     tmp.flush()?;
     tmp.persist(path)?;
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session(id: &str, kind: SessionKind) -> Option<SessionRef> {
+        Some(SessionRef {
+            id: id.to_string(),
+            kind,
+        })
+    }
+
+    #[test]
+    fn record_login_remembers_recent_commands() {
+        let mut state = FerriteState::default();
+        for command in ["a", "b", "c", "d", "e", "b", "f"] {
+            state.record_login("alice", &Session::custom(command).unwrap());
+        }
+        assert_eq!(state.recent_commands, ["f", "b", "e", "d", "c"]);
+        assert_eq!(
+            state.user("alice").unwrap().last_command.as_deref(),
+            Some("f")
+        );
+
+        let sway = Session {
+            id: String::from("sway"),
+            name: String::from("Sway"),
+            kind: SessionKind::Wayland,
+            ..Session::default()
+        };
+        state.record_login("bob", &sway);
+        assert_eq!(state.last_user.as_deref(), Some("bob"));
+        let bob = state.user("bob").unwrap();
+        assert_eq!(bob.last_session, session("sway", SessionKind::Wayland));
+        assert!(bob.last_session.as_ref().unwrap().matches(&sway));
+        assert_eq!(bob.last_command, None);
+        assert!(bob.last_login.is_some());
+    }
+
+    #[test]
+    fn parse_current_version() {
+        let state = parse_state(
+            r#"{"version":2,"last_user":"alice","users":{"alice":{"last_session":{"id":"sway","kind":"wayland"}}},"recent_commands":["zsh"]}"#,
+        )
+        .unwrap();
+        assert_eq!(state.last_user.as_deref(), Some("alice"));
+        assert_eq!(
+            state.user("alice").unwrap().last_session,
+            session("sway", SessionKind::Wayland)
+        );
+        assert_eq!(state.recent_commands, ["zsh"]);
+    }
+
+    #[test]
+    fn migrate_unversioned_and_v1() {
+        let sessions = [
+            Session {
+                id: String::from("river"),
+                name: String::from("River"),
+                ..Session::default()
+            },
+            Session {
+                id: String::from("sway"),
+                name: String::from("Sway"),
+                ..Session::default()
+            },
+        ];
+
+        for contents in [
+            r#"{"last_user":"alice","last_session":"Sway"}"#,
+            r#"{"version":1,"last_user":"alice","last_session":"Sway"}"#,
+        ] {
+            let state = parse_state(contents).unwrap();
+            assert_eq!(state.version, STATE_VERSION);
+            assert_eq!(state.last_user.as_deref(), Some("alice"));
+            let alice = state.user("alice").unwrap();
+            assert_eq!(alice.last_session_name.as_deref(), Some("Sway"));
+            assert_eq!(alice.session_position(&sessions), Some(1));
+        }
+
+        let state = parse_state(r#"{"version":1,"last_user":null,"last_session":"Sway"}"#).unwrap();
+        assert!(state.users.is_empty());
+    }
+
+    #[test]
+    fn session_position_prefers_the_id() {
+        let mut state =
+            parse_state(r#"{"version":1,"last_user":"alice","last_session":"Sway"}"#).unwrap();
+        let sessions = [
+            Session {
+                id: String::from("sway"),
+                name: String::from("Sway"),
+                kind: SessionKind::X11,
+                ..Session::default()
+            },
+            Session {
+                id: String::from("sway"),
+                name: String::from("Sway"),
+                kind: SessionKind::Wayland,
+                ..Session::default()
+            },
+        ];
+        assert_eq!(
+            state.user("alice").unwrap().session_position(&sessions),
+            Some(0)
+        );
+
+        state.record_login("alice", &sessions[1]);
+        let alice = state.user("alice").unwrap();
+        assert_eq!(alice.last_session_name, None);
+        assert_eq!(alice.session_position(&sessions), Some(1));
+        assert_eq!(alice.session_position(&sessions[..1]), None);
+    }
+
+    #[test]
+    fn reject_newer_and_malformed_state() {
+        let err = parse_state(r#"{"version":3}"#).unwrap_err();
+        assert!(err.to_string().contains("newer"), "{err}");
+        assert!(parse_state("[]").is_err());
+        assert!(parse_state(r#"{"version":"2"}"#).is_err());
+        assert!(parse_state(r#"{"version":2,"users":{"alice":"Sway"}}"#).is_err());
+        assert!(parse_state("{").is_err());
+    }
+
+    #[test]
+    fn state_path_per_seat() {
+        let config = |per_seat| StateConfig {
+            path: PathBuf::from("/var/lib/ferrite/state.json"),
+            per_seat,
+        };
+        let path = |seat: Option<&str>, per_seat| {
+            // SAFETY: no other test reads or writes XDG_SEAT.
+            unsafe {
+                match seat {
+                    Some(seat) => env::set_var("XDG_SEAT", seat),
+                    None => env::remove_var("XDG_SEAT"),
+                }
+            }
+            state_path(&config(per_seat))
+        };
+
+        assert_eq!(
+            path(Some("seat1"), true),
+            Path::new("/var/lib/ferrite/state-seat1.json")
+        );
+        assert_eq!(
+            path(Some("seat1"), false),
+            Path::new("/var/lib/ferrite/state.json")
+        );
+        assert_eq!(
+            path(Some("../x"), true),
+            Path::new("/var/lib/ferrite/state.json")
+        );
+        assert_eq!(
+            path(Some(""), true),
+            Path::new("/var/lib/ferrite/state.json")
+        );
+        assert_eq!(path(None, true), Path::new("/var/lib/ferrite/state.json"));
+    }
+
+    #[test]
+    fn update_state_keeps_other_users() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state.json");
+        let sway = Session {
+            id: String::from("sway"),
+            kind: SessionKind::Wayland,
+            ..Session::default()
+        };
+        let zsh = Session {
+            id: String::from("zsh"),
+            kind: SessionKind::Custom,
+            ..Session::default()
+        };
+
+        update_state(&path, |state| state.record_login("alice", &sway)).unwrap();
+        let state = update_state(&path, |state| state.record_login("bob", &zsh)).unwrap();
+        assert_eq!(state.last_user.as_deref(), Some("bob"));
+        assert_eq!(state.recent_commands, ["zsh"]);
+
+        let (state, notice) = read_state(&path).unwrap();
+        assert!(notice.is_none());
+        assert_eq!(
+            state.user("alice").unwrap().last_session,
+            session("sway", SessionKind::Wayland)
+        );
+        assert_eq!(
+            state.user("bob").unwrap().last_command.as_deref(),
+            Some("zsh")
+        );
+    }
+
+    #[test]
+    fn update_state_fails_on_unreadable_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state.json");
+        fs::create_dir(&path).unwrap();
+
+        assert!(update_state(&path, |state| state.last_user = None).is_err());
+        assert!(path.is_dir());
+    }
+}
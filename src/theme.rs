@@ -0,0 +1,572 @@
+use anyhow::{Context, bail};
+use ratatui::{
+    style::{Color, Modifier},
+    symbols::border,
+    widgets::BorderType,
+};
+use serde::{Deserialize, Deserializer, de};
+use std::{env, path::Path};
+use toml::{Table, Value};
+
+use crate::config::{merge, read_table};
+
+/// Built-in themes by name, in the same format as theme files.
+const BUILTIN: [(&str, &str); 4] = [
+    (
+        "default",
+        r##"
+background = "black"
+foreground = "white"
+border = "white"
+focused_border = "yellow"
+title = "white"
+error = "red"
+warning = "yellow"
+hint = "white"
+mask = "white"
+header_border = "plain"
+frame_border = "plain"
+box_border = "double"
+field_border = "plain"
+footer_border = "plain"
+palette = [
+    "#1d1f21", "#cc6666", "#b5bd68", "#f0c674",
+    "#81a2be", "#b294bb", "#8abeb7", "#c5c8c6",
+    "#666666", "#d54e53", "#b9ca4a", "#e7c547",
+    "#7aa6da", "#c397d8", "#70c0b1", "#eaeaea",
+]
+"##,
+    ),
+    (
+        "nord",
+        r##"
+background = "#2e3440"
+foreground = "#d8dee9"
+border = "#4c566a"
+focused_border = "#88c0d0"
+title = "#81a1c1"
+error = "#bf616a"
+warning = "#ebcb8b"
+hint = "#616e88"
+mask = "#a3be8c"
+header_border = "rounded"
+frame_border = "rounded"
+box_border = "rounded"
+field_border = "rounded"
+footer_border = "rounded"
+palette = [
+    "#2e3440", "#bf616a", "#a3be8c", "#ebcb8b",
+    "#81a1c1", "#616e88", "#88c0d0", "#d8dee9",
+    "#4c566a", "#bf616a", "#a3be8c", "#ebcb8b",
+    "#81a1c1", "#b48ead", "#8fbcbb", "#eceff4",
+]
+"##,
+    ),
+    (
+        "gruvbox",
+        r##"
+background = "#282828"
+foreground = "#ebdbb2"
+border = "#665c54"
+focused_border = "#fabd2f"
+title = "#83a598"
+error = "#fb4934"
+warning = "#fe8019"
+hint = "#928374"
+mask = "#b8bb26"
+header_border = "plain"
+frame_border = "plain"
+box_border = "thick"
+field_border = "plain"
+footer_border = "plain"
+palette = [
+    "#282828", "#cc241d", "#98971a", "#d79921",
+    "#458588", "#665c54", "#689d6a", "#ebdbb2",
+    "#928374", "#fb4934", "#b8bb26", "#fabd2f",
+    "#83a598", "#fe8019", "#8ec07c", "#fbf1c7",
+]
+"##,
+    ),
+    (
+        "dracula",
+        r##"
+background = "#282a36"
+foreground = "#f8f8f2"
+border = "#6272a4"
+focused_border = "#bd93f9"
+title = "#ff79c6"
+error = "#ff5555"
+warning = "#ffb86c"
+hint = "#6272a4"
+mask = "#50fa7b"
+header_border = "rounded"
+frame_border = "rounded"
+box_border = "double"
+field_border = "rounded"
+footer_border = "rounded"
+palette = [
+    "#282a36", "#ff5555", "#50fa7b", "#f1fa8c",
+    "#bd93f9", "#ff79c6", "#8be9fd", "#f8f8f2",
+    "#6272a4", "#ff6e6e", "#69ff94", "#ffb86c",
+    "#d6acff", "#ff92df", "#a4ffff", "#ffffff",
+]
+"##,
+    ),
+];
+
+/// Colours and border types of the greeter. Colours are given by name
+/// (`"light-blue"`), index (`"33"`) or hex (`"#2e3440"`); border types are
+/// `plain`, `rounded`, `double` or `thick`.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Theme {
+    #[serde(deserialize_with = "color")]
+    pub background: Color,
+    #[serde(deserialize_with = "color")]
+    pub foreground: Color,
+    #[serde(deserialize_with = "color")]
+    pub border: Color,
+    /// Border of the field with focus.
+    #[serde(deserialize_with = "color")]
+    pub focused_border: Color,
+    /// Title of the login box.
+    #[serde(deserialize_with = "color")]
+    pub title: Color,
+    #[serde(deserialize_with = "color")]
+    pub error: Color,
+    /// Problems that do not stop a login, such as an unsaved state file.
+    #[serde(deserialize_with = "color")]
+    pub warning: Color,
+    /// Key hints and session descriptions.
+    #[serde(deserialize_with = "color")]
+    pub hint: Color,
+    /// The masked password.
+    #[serde(deserialize_with = "color")]
+    pub mask: Color,
+    #[serde(deserialize_with = "border_type")]
+    pub header_border: BorderType,
+    /// Border around the area holding the login box.
+    #[serde(deserialize_with = "border_type")]
+    pub frame_border: BorderType,
+    /// Border of the login box.
+    #[serde(deserialize_with = "border_type")]
+    pub box_border: BorderType,
+    #[serde(deserialize_with = "border_type")]
+    pub field_border: BorderType,
+    #[serde(deserialize_with = "border_type")]
+    pub footer_border: BorderType,
+    /// Console palette (16 `#rrggbb` colours, ANSI order) set on a Linux VT.
+    #[serde(default, deserialize_with = "palette")]
+    pub palette: Option<Vec<Color>>,
+    /// Added to the focused field, and to errors and warnings, where colour
+    /// alone cannot tell them apart.
+    #[serde(skip)]
+    pub focus_modifier: Modifier,
+    #[serde(skip)]
+    pub alert_modifier: Modifier,
+    /// Draw with 7-bit ASCII only, for consoles without box-drawing glyphs.
+    #[serde(skip)]
+    pub ascii: bool,
+}
+
+/// How many colours the terminal can show.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorDepth {
+    #[serde(rename = "truecolor")]
+    TrueColor,
+    #[serde(rename = "256")]
+    Indexed,
+    #[serde(rename = "16")]
+    Ansi,
+    #[serde(rename = "mono")]
+    Mono,
+}
+
+impl ColorDepth {
+    /// Guesses the colour depth from `COLORTERM` and `TERM`. The Linux console
+    /// (`vt`) shows 16 colours whatever `TERM` says.
+    pub fn detect(vt: bool) -> ColorDepth {
+        let term = env::var("TERM").unwrap_or_default();
+        let colorterm = env::var("COLORTERM").unwrap_or_default();
+
+        if vt || term == "linux" {
+            ColorDepth::Ansi
+        } else if matches!(colorterm.as_str(), "truecolor" | "24bit") || term.ends_with("-direct") {
+            ColorDepth::TrueColor
+        } else if term.contains("256color")
+            || ["foot", "alacritty", "xterm-kitty", "wezterm"].contains(&term.as_str())
+        {
+            ColorDepth::Indexed
+        } else if term.is_empty() || term == "dumb" || term.starts_with("vt") {
+            ColorDepth::Mono
+        } else {
+            ColorDepth::Ansi
+        }
+    }
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Theme::resolve(Table::new()).expect("the default theme is valid")
+    }
+}
+
+impl Theme {
+    pub fn names() -> impl Iterator<Item = &'static str> {
+        BUILTIN.iter().map(|(name, _)| *name)
+    }
+
+    /// Builds a theme from a `[theme]` table: the built-in theme named by `base`
+    /// (default: `default`) with the table's other keys replacing its values.
+    pub fn resolve(mut table: Table) -> anyhow::Result<Self> {
+        let base = match table.remove("base") {
+            None => String::from("default"),
+            Some(Value::String(base)) => base,
+            Some(_) => bail!("theme base must be a theme name"),
+        };
+        let Some((_, builtin)) = BUILTIN.iter().find(|(name, _)| *name == base) else {
+            bail!(
+                "unknown theme `{base}`, expected one of: {}",
+                Theme::names().collect::<Vec<_>>().join(", ")
+            );
+        };
+
+        let mut theme: Table = toml::from_str(builtin).expect("built-in themes are valid TOML");
+        merge(&mut theme, table);
+        Theme::deserialize(Value::Table(theme)).context("invalid theme")
+    }
+
+    /// The theme for a console using `palette`: RGB colours are replaced by the
+    /// nearest palette entry, so that they are drawn in exactly that colour.
+    pub fn on_palette(&self, palette: &[Color]) -> Theme {
+        let map = |color: Color| nearest(color, palette);
+        Theme {
+            background: map(self.background),
+            foreground: map(self.foreground),
+            border: map(self.border),
+            focused_border: map(self.focused_border),
+            title: map(self.title),
+            error: map(self.error),
+            warning: map(self.warning),
+            hint: map(self.hint),
+            mask: map(self.mask),
+            ..self.clone()
+        }
+    }
+
+    /// The characters for a border of `border_type`. In ASCII, double and thick
+    /// borders are drawn with `=` to keep them apart from plain ones.
+    pub fn border_set(&self, border_type: BorderType) -> border::Set {
+        if !self.ascii {
+            return border_type.to_border_set();
+        }
+        let horizontal = match border_type {
+            BorderType::Double | BorderType::Thick => "=",
+            _ => "-",
+        };
+        border::Set {
+            top_left: "+",
+            top_right: "+",
+            bottom_left: "+",
+            bottom_right: "+",
+            vertical_left: "|",
+            vertical_right: "|",
+            horizontal_top: horizontal,
+            horizontal_bottom: horizontal,
+        }
+    }
+
+    /// The theme with every colour replaced by the nearest one `depth` can show.
+    /// With 16 colours, the theme's palette says which ANSI colour each RGB
+    /// colour stands for, and ANSI colours are kept as they are. Without
+    /// colours, focus is shown in bold and alerts in reverse video.
+    pub fn downgrade(&self, depth: ColorDepth) -> Theme {
+        let xterm = xterm_palette();
+        let ansi = self.palette.as_deref().unwrap_or(&xterm[..16]);
+        let map = |color: Color| match depth {
+            ColorDepth::TrueColor => color,
+            ColorDepth::Indexed => nearest(color, &xterm),
+            ColorDepth::Ansi => match color {
+                Color::Indexed(i) if i < 16 => ANSI[usize::from(i)],
+                Color::Indexed(i) => match nearest(xterm[usize::from(i)], ansi) {
+                    Color::Indexed(i) => ANSI[usize::from(i)],
+                    color => color,
+                },
+                Color::Rgb(..) => match nearest(color, ansi) {
+                    Color::Indexed(i) => ANSI[usize::from(i)],
+                    color => color,
+                },
+                named => named,
+            },
+            ColorDepth::Mono => Color::Reset,
+        };
+
+        let mut theme = Theme {
+            background: map(self.background),
+            foreground: map(self.foreground),
+            border: map(self.border),
+            focused_border: map(self.focused_border),
+            title: map(self.title),
+            error: map(self.error),
+            warning: map(self.warning),
+            hint: map(self.hint),
+            mask: map(self.mask),
+            ..self.clone()
+        };
+        if depth == ColorDepth::Mono {
+            theme.focus_modifier = Modifier::BOLD;
+            theme.alert_modifier = Modifier::REVERSED;
+        }
+        theme
+    }
+}
+
+/// Whether the locale's character set is UTF-8, taken from `LC_ALL`, `LC_CTYPE`
+/// or `LANG` like the C library does, or `None` if none of them is set. The C
+/// locale is ASCII.
+pub fn utf8_locale() -> Option<bool> {
+    let locale = ["LC_ALL", "LC_CTYPE", "LANG"]
+        .iter()
+        .filter_map(|var| env::var(var).ok())
+        .find(|value| !value.is_empty())?;
+
+    let locale = locale
+        .split_once('@')
+        .map_or(locale.as_str(), |(locale, _)| locale);
+    let codeset = locale.split_once('.').map_or("", |(_, codeset)| codeset);
+    Some(codeset.replace('-', "").eq_ignore_ascii_case("utf8"))
+}
+
+/// `text` in 7-bit ASCII, with `…` spelled out and other characters replaced
+/// by [`ascii_char`].
+pub fn ascii_text(text: &str) -> String {
+    let mut ascii = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '…' => ascii.push_str("..."),
+            c => ascii.push(ascii_char(c)),
+        }
+    }
+    ascii
+}
+
+/// A one-character ASCII stand-in for `c`: quotes, dashes and spaces by their
+/// ASCII forms, anything else by `?`.
+pub fn ascii_char(c: char) -> char {
+    match c {
+        c if c.is_ascii() => c,
+        '‘' | '’' | '‚' | '′' => '\'',
+        '“' | '”' | '„' | '″' => '"',
+        '‐' | '‑' | '‒' | '–' | '—' | '−' => '-',
+        '\u{a0}' | '\u{2002}'..='\u{200a}' => ' ',
+        '…' => '.',
+        _ => '?',
+    }
+}
+
+/// The 16 ANSI colours, by index.
+const ANSI: [Color; 16] = [
+    Color::Black,
+    Color::Red,
+    Color::Green,
+    Color::Yellow,
+    Color::Blue,
+    Color::Magenta,
+    Color::Cyan,
+    Color::Gray,
+    Color::DarkGray,
+    Color::LightRed,
+    Color::LightGreen,
+    Color::LightYellow,
+    Color::LightBlue,
+    Color::LightMagenta,
+    Color::LightCyan,
+    Color::White,
+];
+
+/// xterm's default 256-colour palette: the ANSI colours, a 6x6x6 colour cube
+/// and a grey ramp.
+fn xterm_palette() -> Vec<Color> {
+    const ANSI_RGB: [(u8, u8, u8); 16] = [
+        (0, 0, 0),
+        (205, 0, 0),
+        (0, 205, 0),
+        (205, 205, 0),
+        (0, 0, 238),
+        (205, 0, 205),
+        (0, 205, 205),
+        (229, 229, 229),
+        (127, 127, 127),
+        (255, 0, 0),
+        (0, 255, 0),
+        (255, 255, 0),
+        (92, 92, 255),
+        (255, 0, 255),
+        (0, 255, 255),
+        (255, 255, 255),
+    ];
+    let level = |i: u8| if i == 0 { 0 } else { 55 + 40 * i };
+
+    let ansi = ANSI_RGB.iter().map(|&(r, g, b)| Color::Rgb(r, g, b));
+    let cube = (0..216u8).map(|i| Color::Rgb(level(i / 36), level(i / 6 % 6), level(i % 6)));
+    let grey = (0..24u8).map(|i| Color::Rgb(8 + 10 * i, 8 + 10 * i, 8 + 10 * i));
+    ansi.chain(cube).chain(grey).collect()
+}
+
+/// The palette entry closest to an RGB `color`, as an indexed colour.
+fn nearest(color: Color, palette: &[Color]) -> Color {
+    let Color::Rgb(r, g, b) = color else {
+        return color;
+    };
+    let distance = |entry: &Color| match *entry {
+        Color::Rgb(er, eg, eb) => [(r, er), (g, eg), (b, eb)]
+            .iter()
+            .map(|&(a, b)| (i32::from(a) - i32::from(b)).pow(2))
+            .sum(),
+        _ => i32::MAX,
+    };
+
+    palette
+        .iter()
+        .enumerate()
+        .min_by_key(|(_, entry)| distance(entry))
+        .map_or(color, |(i, _)| Color::Indexed(i as u8))
+}
+
+/// The theme selected by `--theme`: a built-in theme's name, or a theme file,
+/// which may itself name a `base` theme. It replaces the configured `[theme]`.
+pub fn load_theme(theme: &Path) -> anyhow::Result<Theme> {
+    theme_table(theme).and_then(Theme::resolve)
+}
+
+fn theme_table(theme: &Path) -> anyhow::Result<Table> {
+    if let Some(name) = theme.to_str()
+        && Theme::names().any(|builtin| builtin == name)
+    {
+        let mut table = Table::new();
+        table.insert(String::from("base"), Value::String(name.to_string()));
+        return Ok(table);
+    }
+
+    if !theme.exists() {
+        bail!(
+            "{} is neither a theme file nor one of: {}",
+            theme.display(),
+            Theme::names().collect::<Vec<_>>().join(", ")
+        );
+    }
+    read_table(theme)
+}
+
+/// Deserializes a `[theme]` table into the resolved theme.
+pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Theme, D::Error> {
+    let table = Table::deserialize(deserializer)?;
+    Theme::resolve(table).map_err(|e| de::Error::custom(format!("{e:#}")))
+}
+
+fn color<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Color, D::Error> {
+    let name = String::deserialize(deserializer)?;
+    name.parse()
+        .map_err(|_| de::Error::custom(format!("unknown colour `{name}`")))
+}
+
+fn palette<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Vec<Color>>, D::Error> {
+    let names = Vec::<String>::deserialize(deserializer)?;
+    if names.len() != 16 {
+        return Err(de::Error::custom(format!(
+            "palette needs 16 colours, not {}",
+            names.len()
+        )));
+    }
+
+    names
+        .iter()
+        .map(|name| match name.parse() {
+            Ok(Color::Rgb(r, g, b)) => Ok(Color::Rgb(r, g, b)),
+            _ => Err(de::Error::custom(format!(
+                "palette colour `{name}` is not #rrggbb"
+            ))),
+        })
+        .collect::<Result<_, _>>()
+        .map(Some)
+}
+
+fn border_type<'de, D: Deserializer<'de>>(deserializer: D) -> Result<BorderType, D::Error> {
+    let name = String::deserialize(deserializer)?;
+    match name.as_str() {
+        "plain" => Ok(BorderType::Plain),
+        "rounded" => Ok(BorderType::Rounded),
+        "double" => Ok(BorderType::Double),
+        "thick" => Ok(BorderType::Thick),
+        _ => Err(de::Error::custom(format!(
+            "unknown border type `{name}`, expected plain, rounded, double or thick"
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn builtin(name: &str) -> Theme {
+        let mut table = Table::new();
+        table.insert(String::from("base"), Value::String(name.to_string()));
+        Theme::resolve(table).unwrap()
+    }
+
+    fn colors(theme: &Theme) -> [Color; 9] {
+        [
+            theme.background,
+            theme.foreground,
+            theme.border,
+            theme.focused_border,
+            theme.title,
+            theme.error,
+            theme.warning,
+            theme.hint,
+            theme.mask,
+        ]
+    }
+
+    #[test]
+    fn downgrade_keeps_console_palette_entries() {
+        for name in Theme::names() {
+            let theme = builtin(name);
+            let palette = theme.palette.clone().unwrap();
+            let on_console = theme.on_palette(&palette);
+            let downgraded = on_console.downgrade(ColorDepth::Ansi);
+
+            for (before, after) in colors(&on_console).into_iter().zip(colors(&downgraded)) {
+                let expected = match before {
+                    Color::Indexed(i) => ANSI[usize::from(i)],
+                    named => named,
+                };
+                assert_eq!(after, expected, "theme {name}");
+            }
+        }
+    }
+
+    #[test]
+    fn masked_password_stays_visible_on_console() {
+        for name in Theme::names() {
+            let theme = builtin(name);
+            let palette = theme.palette.clone().unwrap();
+            let downgraded = theme.on_palette(&palette).downgrade(ColorDepth::Ansi);
+            assert_ne!(downgraded.mask, downgraded.background, "theme {name}");
+        }
+    }
+
+    #[test]
+    fn downgrade_maps_rgb_to_theme_palette() {
+        let theme = builtin("nord").downgrade(ColorDepth::Ansi);
+        assert_eq!(theme.error, Color::Red);
+        assert_eq!(theme.focused_border, Color::Cyan);
+    }
+
+    #[test]
+    fn ascii_text_replaces_unicode() {
+        assert_eq!(ascii_text("Custom command…"), "Custom command...");
+        assert_eq!(ascii_text("“Edited” – ok"), "\"Edited\" - ok");
+        assert_eq!(ascii_text("Grüße"), "Gr??e");
+    }
+}
@@ -1,17 +1,25 @@
 use crate::app::{AppState, AuthState};
 use crate::auth::AuthError;
+use crate::config::{Config, LabelConfig};
+use crate::desktop::join_exec;
+use crate::theme::ascii_char;
 use crate::widgets::widget::InputField;
 use ratatui::widgets::Padding;
 use ratatui::{
     Frame,
     layout::{Alignment, Constraint, Layout, Rect},
     style::{Color, Style, Stylize},
-    widgets::{Block, BorderType, Paragraph, Widget, block::Position},
+    text::Line,
+    widgets::{Block, Clear, Paragraph, Widget, block::Position},
 };
 
-pub fn render(frame: &mut Frame, app_state: &mut AppState) {
-    let fg_color = Color::White;
-    let bg_color = Color::Black;
+const SPINNER: [char; 10] = ['⠋', '⠙', '⠹', '⠸', '⠼', '⠴', '⠦', '⠧', '⠇', '⠏'];
+const ASCII_SPINNER: [char; 4] = ['|', '/', '-', '\\'];
+
+pub fn render(frame: &mut Frame, app_state: &mut AppState, config: &Config) {
+    let theme = &config.theme;
+    let fg_color = theme.foreground;
+    let bg_color = theme.background;
 
     let [header_area, content_area, footer_area] = Layout::vertical([
         Constraint::Length(3),
@@ -27,36 +35,71 @@ pub fn render(frame: &mut Frame, app_state: &mut AppState) {
     );
 
     Block::bordered()
-        .border_type(BorderType::Plain)
-        .fg(fg_color)
+        .border_set(theme.border_set(theme.frame_border))
+        .fg(theme.border)
         .render(content_area, frame.buffer_mut());
 
-    Paragraph::new("F1 - Shutdown | F2 - Reboot")
+    Paragraph::new(power_hints(config))
+        .fg(theme.hint)
         .block(
             Block::bordered()
-                .border_type(BorderType::Plain)
+                .border_set(theme.border_set(theme.header_border))
+                .border_style(Style::default().fg(theme.border))
                 .padding(Padding::symmetric(2, 0))
-                .fg(fg_color),
+                .title(Line::from(config.ui.greeting.clone().unwrap_or_default()).centered())
+                .title_style(Style::default().fg(theme.title)),
         )
         .render(header_area, frame.buffer_mut());
 
-    let (title_txt, error_msg) = get_title_and_error(&app_state.auth_state);
-
-    let (footer_color, footer_text): (Color, &str) = match &error_msg {
-        Some(msg) => (Color::Red, msg.as_str()),
-        None => (fg_color, app_state.hostname.as_str()),
+    let (title_txt, error_msg) = get_title_and_error(&app_state.auth_state, &config.labels);
+    let title_style = match app_state.auth_state {
+        AuthState::Failed(_) => Style::default()
+            .fg(theme.error)
+            .add_modifier(theme.alert_modifier),
+        _ => Style::default().fg(theme.title),
+    };
+    let title_txt = match app_state.auth_state {
+        AuthState::Authenticating => {
+            let spinner: &[char] = if theme.ascii {
+                &ASCII_SPINNER
+            } else {
+                &SPINNER
+            };
+            let frame = spinner[app_state.tick % spinner.len()];
+            format!("{title_txt} {frame}")
+        }
+        _ => title_txt.to_string(),
     };
 
+    // Errors and warnings colour the whole footer, border included.
+    let (footer_alert, footer_text): (Option<Color>, &str) =
+        match (&error_msg, &app_state.pam_message) {
+            (Some(msg), _) => (Some(theme.error), msg.as_str()),
+            (None, Some(msg)) if msg.error => (Some(theme.error), msg.text.as_str()),
+            (None, Some(msg)) => (None, msg.text.as_str()),
+            (None, None) => match &app_state.state_notice {
+                Some(notice) => (Some(theme.warning), notice.as_str()),
+                None => (None, app_state.hostname.as_str()),
+            },
+        };
+
+    let footer_style = match footer_alert {
+        Some(color) => Style::default()
+            .fg(color)
+            .add_modifier(theme.alert_modifier),
+        None => Style::default().fg(fg_color),
+    };
     Paragraph::new(footer_text)
+        .style(footer_style)
         .block(
             Block::bordered()
-                .border_type(BorderType::Plain)
-                .padding(Padding::symmetric(2, 0))
-                .fg(footer_color),
+                .border_set(theme.border_set(theme.footer_border))
+                .border_style(Style::default().fg(footer_alert.unwrap_or(theme.border)))
+                .padding(Padding::symmetric(2, 0)),
         )
         .render(footer_area, frame.buffer_mut());
 
-    let main_block = centered_rect(55, 15, content_area);
+    let main_block = centered_rect(config.ui.width, config.ui.height, content_area);
 
     let [session_area, username_area, password_area] = Layout::vertical([
         Constraint::Length(3),
@@ -67,34 +110,92 @@ pub fn render(frame: &mut Frame, app_state: &mut AppState) {
     .spacing(1)
     .areas(main_block);
 
+    // Describe the highlighted session while the session field has focus.
+    let session_hint = match app_state.edited_exec() {
+        Some(exec) => format!("{}: {}", config.labels.edited, join_exec(exec)),
+        None => (app_state.focus_index == app_state.session.index)
+            .then(|| app_state.session.items.get(app_state.session.selected_idx))
+            .flatten()
+            .and_then(|session| session.comment.clone())
+            .unwrap_or_default(),
+    };
+
     Block::bordered()
-        .border_type(BorderType::Double)
-        .fg(fg_color)
-        .title(title_txt)
+        .border_set(theme.border_set(theme.box_border))
+        .fg(theme.border)
+        .title(Line::from(title_txt).style(title_style))
+        .title_bottom(Line::from(session_hint).fg(theme.hint).centered())
         .title_position(Position::Top)
         .title_alignment(Alignment::Center)
         .render(main_block, frame.buffer_mut());
 
     app_state
         .username
-        .render(frame, &app_state.focus_index, username_area);
+        .render(frame, &app_state.focus_index, username_area, theme);
     app_state
         .password
-        .render(frame, &app_state.focus_index, password_area);
-    app_state
-        .session
-        .render(frame, &app_state.focus_index, session_area);
+        .render(frame, &app_state.focus_index, password_area, theme);
+    if app_state.custom_selected() {
+        app_state
+            .command
+            .render(frame, &app_state.focus_index, session_area, theme);
+    } else {
+        app_state
+            .session
+            .render(frame, &app_state.focus_index, session_area, theme);
+    }
+
+    if let Some(editor) = &app_state.editor {
+        let editor_area = centered_rect(main_block.width + 10, 3, content_area);
+        frame.render_widget(Clear, editor_area);
+        editor.render(frame, &editor.index, editor_area, theme);
+    }
+
+    // Labels are already ASCII; session names, PAM messages and typed text may not be.
+    if theme.ascii {
+        for cell in &mut frame.buffer_mut().content {
+            if !cell.symbol().is_ascii() {
+                let c = cell.symbol().chars().next().map_or('?', ascii_char);
+                cell.set_char(c);
+            }
+        }
+    }
+}
+
+/// Header text listing the power keys whose commands are configured.
+fn power_hints(config: &Config) -> String {
+    [
+        (
+            &config.keys.shutdown,
+            &config.labels.shutdown,
+            &config.power.shutdown,
+        ),
+        (
+            &config.keys.reboot,
+            &config.labels.reboot,
+            &config.power.reboot,
+        ),
+    ]
+    .into_iter()
+    .filter(|(_, _, cmd)| !cmd.is_empty())
+    .map(|(key, label, _)| format!("{key} - {label}"))
+    .collect::<Vec<_>>()
+    .join(" | ")
 }
 
-fn get_title_and_error(auth_state: &AuthState) -> (&'static str, Option<String>) {
+fn get_title_and_error<'a>(
+    auth_state: &AuthState,
+    labels: &'a LabelConfig,
+) -> (&'a str, Option<String>) {
     match auth_state {
-        AuthState::None => ("Authenticate", None),
-        AuthState::Authenticating => ("Authenticating", None),
+        AuthState::None => (&labels.title, None),
+        AuthState::Authenticating => (&labels.authenticating, None),
         AuthState::Failed(auth_error) => match auth_error {
-            AuthError::AuthFailed(_) => ("Authentication Failed", None),
+            AuthError::AuthFailed(_) => (&labels.failed, None),
             AuthError::Connection(e) | AuthError::Protocol(e) | AuthError::InvalidSession(e) => {
-                ("Authenticate", Some(e.to_string()))
+                (&labels.title, Some(e.to_string()))
             }
+            AuthError::NoUser => (&labels.title, Some(auth_error.to_string())),
         },
     }
 }
@@ -1,128 +1,370 @@
+use color_eyre::Result;
+use serde::{Deserialize, Serialize};
 use std::{
-    env,
+    collections::{BTreeMap, HashSet},
+    env, fmt,
     fs::{self, File},
     io::{self, BufRead},
+    path::{Path, PathBuf},
 };
-use color_eyre::Result;
+
+use crate::config::Config;
+use crate::desktop::{DesktopEntry, Locale, split_command, split_exec, try_exec};
+use crate::x11;
+
+#[derive(
+    Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum SessionKind {
+    #[default]
+    Wayland,
+    X11,
+    /// A plain terminal program such as a shell.
+    Tty,
+    /// A command line typed in at the greeter.
+    Custom,
+}
+
+impl SessionKind {
+    /// Value of `XDG_SESSION_TYPE` for sessions of this kind.
+    pub fn session_type(self) -> &'static str {
+        match self {
+            SessionKind::Wayland => "wayland",
+            SessionKind::X11 => "x11",
+            SessionKind::Tty | SessionKind::Custom => "tty",
+        }
+    }
+}
+
+impl fmt::Display for SessionKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SessionKind::Wayland => write!(f, "Wayland"),
+            SessionKind::X11 => write!(f, "X11"),
+            SessionKind::Tty => write!(f, "TTY"),
+            SessionKind::Custom => write!(f, "custom"),
+        }
+    }
+}
 
 #[derive(Debug, Default, Clone)]
 pub struct Session {
+    /// Desktop-file ID, the file name without `.desktop`.
+    pub id: String,
     pub name: String,
-    pub exec: String,
+    /// Command line split into argv, as sent to greetd. Empty for the login-shell
+    /// fallback, which is resolved for the chosen user at login.
+    pub exec: Vec<String>,
+    pub kind: SessionKind,
+    pub desktop_names: Vec<String>,
+    pub comment: Option<String>,
+    #[allow(dead_code, reason = "not drawn by the terminal UI")]
+    pub icon: Option<String>,
 }
 
-fn read_sessions_in_dir(dir: &str) -> Result<Vec<Session>> {
-    let mut sessions = Vec::new();
-    let entries = match fs::read_dir(dir) {
-        Ok(e) => e,
-        Err(_) => return Ok(sessions),
-    };
+impl Session {
+    /// A session running a command line typed in by the user, tokenized like `Exec`.
+    pub fn custom(line: &str) -> Option<Self> {
+        let line = line.trim();
+        Some(Self {
+            id: line.to_string(),
+            name: line.to_string(),
+            exec: split_exec(line)?,
+            kind: SessionKind::Custom,
+            ..Self::default()
+        })
+    }
+
+    /// The "Custom command…" selector entry, which turns into a command input.
+    pub fn custom_entry(label: &str) -> Self {
+        Self {
+            name: label.to_string(),
+            kind: SessionKind::Custom,
+            ..Self::default()
+        }
+    }
 
-    for entry in entries {
-        let entry = match entry {
-            Ok(e) => e,
-            Err(_) => return Ok(sessions),
+    /// Text shown in the session selector.
+    pub fn label(&self) -> String {
+        if self.kind == SessionKind::Custom && self.exec.is_empty() {
+            return self.name.clone();
+        }
+        format!("{} ({})", self.name, self.kind)
+    }
+
+    /// Whether the kind's wrapper (or the native X11 launcher) applies to this session.
+    pub fn wrapped(&self, config: &Config) -> bool {
+        config
+            .sessions
+            .get(&self.id)
+            .and_then(|s| s.wrap)
+            .unwrap_or(true)
+    }
+
+    /// Prepends the configured wrapper for this session's kind to `cmd`, unless
+    /// wrapping is turned off for this session.
+    pub fn wrap(&self, cmd: Vec<String>, config: &Config) -> Vec<String> {
+        let wrapper = match self.kind {
+            SessionKind::Wayland => &config.wrappers.wayland,
+            SessionKind::X11 => &config.wrappers.x11,
+            SessionKind::Tty | SessionKind::Custom => &config.wrappers.tty,
         };
 
-        let path = entry.path();
+        if wrapper.is_empty() || !self.wrapped(config) {
+            return cmd;
+        }
 
-        if path.extension().and_then(|v| v.to_str()) != Some("desktop") {
-            continue;
+        let mut wrapped = wrapper.clone();
+        if wrapper.last().is_some_and(|arg| arg == "-c") {
+            wrapped.push(format!("exec {}", shell_join(&cmd)));
+        } else {
+            wrapped.extend(cmd);
         }
+        wrapped
+    }
 
-        let file = match File::open(path) {
-            Ok(e) => e,
-            Err(_) => continue,
-        };
+    /// Command and environment for greetd to start this session as `username`:
+    /// the login shell for entries without a command, X11 sessions through
+    /// ferrite's own launcher when enabled, and otherwise the kind's wrapper.
+    pub fn launch(
+        &self,
+        username: &str,
+        config: &Config,
+    ) -> anyhow::Result<(Vec<String>, Vec<String>)> {
+        let mut cmd = self.exec.clone();
+        if cmd.is_empty() {
+            cmd.extend(login_shell(username));
+        }
 
-        let reader = io::BufReader::new(file);
-        let mut session = Session::default();
-        for line in reader.lines().map_while(Result::ok) {
-            if let Some((key, value)) = line.split_once("=") {
-                match key.trim() {
-                    "Name" => session.name = value.to_string(),
-                    "Exec" => session.exec = value.to_string(),
-                    _ => {}
-                }
-            }
-            if !session.name.is_empty() && !session.exec.is_empty() {
-                break;
+        let env = self.env(config);
+        if self.kind == SessionKind::X11 && config.x11.native && self.wrapped(config) {
+            return Ok((x11::native_session(cmd)?, env));
+        }
+        Ok((self.wrap(cmd, config), env))
+    }
+
+    /// Environment for `StartSession`: the XDG session variables derived from the
+    /// entry, then the configured variables for all sessions and for this one.
+    pub fn env(&self, config: &Config) -> Vec<String> {
+        let mut env = BTreeMap::new();
+        env.insert("XDG_SESSION_TYPE", self.kind.session_type().to_string());
+        if matches!(self.kind, SessionKind::Wayland | SessionKind::X11) {
+            env.insert("XDG_SESSION_DESKTOP", self.id.clone());
+            env.insert("DESKTOP_SESSION", self.id.clone());
+        }
+        if !self.desktop_names.is_empty() {
+            env.insert("XDG_CURRENT_DESKTOP", self.desktop_names.join(":"));
+        }
+
+        let session_env = config.sessions.get(&self.id).map(|s| &s.env);
+        for (key, value) in config.env.iter().chain(session_env.into_iter().flatten()) {
+            env.insert(key, value.clone());
+        }
+
+        env.into_iter()
+            .map(|(key, value)| format!("{key}={value}"))
+            .collect()
+    }
+}
+
+/// Joins argv into a shell command line, single-quoting every argument.
+fn shell_join(args: &[String]) -> String {
+    args.iter()
+        .map(|arg| format!("'{}'", arg.replace('\'', r"'\''")))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Reads the desktop entries in `dir` as `(desktop-file ID, entry)` pairs,
+/// ordered by file name.
+fn read_entries_in_dir(dir: &Path) -> Vec<(String, DesktopEntry)> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+
+    let mut paths: Vec<PathBuf> = entries
+        .map_while(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().and_then(|v| v.to_str()) == Some("desktop"))
+        .collect();
+    paths.sort();
+
+    paths
+        .into_iter()
+        .filter_map(|path| {
+            let contents = fs::read_to_string(&path).ok()?;
+            let id = path.file_stem()?.to_string_lossy().into_owned();
+            Some((id, DesktopEntry::parse(&contents)))
+        })
+        .collect()
+}
+
+fn session_from_entry(
+    id: String,
+    kind: SessionKind,
+    entry: &DesktopEntry,
+    locale: Option<&Locale>,
+) -> Option<Session> {
+    let entry_type = entry.string("Type");
+    if !matches!(
+        entry_type.as_deref(),
+        None | Some("Application" | "XSession")
+    ) {
+        return None;
+    }
+
+    if entry.boolean("Hidden") || entry.boolean("NoDisplay") {
+        return None;
+    }
+
+    if let Some(program) = entry.string("TryExec")
+        && !try_exec(&program)
+    {
+        return None;
+    }
+
+    Some(Session {
+        id,
+        name: entry
+            .localized("Name", locale)
+            .filter(|name| !name.is_empty())?,
+        exec: split_exec(&entry.string("Exec")?)?,
+        kind,
+        desktop_names: entry.list("DesktopNames"),
+        comment: entry.localized("Comment", locale),
+        icon: entry.string("Icon"),
+    })
+}
+
+/// Reads greetd's `environments` file: one session command per line, as used by
+/// other greetd greeters. Each line becomes a TTY session named after the command.
+fn read_environments(path: &Path) -> Vec<Session> {
+    let Ok(contents) = fs::read_to_string(path) else {
+        return Vec::new();
+    };
+
+    contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| {
+            Some(Session {
+                id: line.to_string(),
+                name: line.to_string(),
+                exec: split_command(line)?,
+                kind: SessionKind::Tty,
+                ..Session::default()
+            })
+        })
+        .collect()
+}
+
+/// Session offered when discovery finds nothing: the configured fallback command,
+/// or else the user's login shell.
+pub fn fallback_session(config: &Config) -> Session {
+    let command = config.discovery.fallback_command.as_deref();
+
+    match command.and_then(|line| Some((line, split_command(line)?))) {
+        Some((line, exec)) => Session {
+            id: String::from("fallback"),
+            name: line.to_string(),
+            exec,
+            kind: SessionKind::Tty,
+            ..Session::default()
+        },
+        None => Session {
+            id: String::from("shell"),
+            name: String::from("Login shell"),
+            kind: SessionKind::Tty,
+            ..Session::default()
+        },
+    }
+}
+
+/// Session directories in precedence order: `XDG_DATA_HOME`, then
+/// `XDG_DATA_DIRS`, then the traditional locations. Configured directories
+/// replace these for their kind.
+fn session_dirs(config: &Config) -> Vec<(PathBuf, SessionKind)> {
+    let mut data_dirs: Vec<PathBuf> = Vec::new();
+
+    match env::var_os("XDG_DATA_HOME").filter(|dir| !dir.is_empty()) {
+        Some(dir) => data_dirs.push(dir.into()),
+        None => {
+            data_dirs.extend(env::var_os("HOME").map(|home| Path::new(&home).join(".local/share")))
+        }
+    }
+
+    let system_dirs = env::var_os("XDG_DATA_DIRS")
+        .filter(|dirs| !dirs.is_empty())
+        .unwrap_or_else(|| "/usr/local/share:/usr/share".into());
+    data_dirs.extend(env::split_paths(&system_dirs));
+    data_dirs.extend(["/usr/local/share".into(), "/usr/share".into()]);
+
+    let mut dirs = Vec::new();
+    for data_dir in data_dirs {
+        for (subdir, kind) in [
+            ("wayland-sessions", SessionKind::Wayland),
+            ("xsessions", SessionKind::X11),
+        ] {
+            let dir = (data_dir.join(subdir), kind);
+            if !dirs.contains(&dir) {
+                dirs.push(dir);
             }
         }
+    }
+    dirs.push(("/etc/X11/Sessions".into(), SessionKind::X11));
 
-        if !session.name.is_empty() && !session.exec.is_empty() {
-            sessions.push(session);
+    for (kind, configured) in [
+        (SessionKind::Wayland, &config.discovery.wayland_dirs),
+        (SessionKind::X11, &config.discovery.x11_dirs),
+    ] {
+        if let Some(configured) = configured {
+            dirs.retain(|(_, k)| *k != kind);
+            dirs.extend(configured.iter().map(|dir| (dir.clone(), kind)));
         }
     }
 
-    Ok(sessions)
+    dirs
 }
 
-// read_sessions_in_dir(dir: &str) -> anyhow::Result<Vec<Session>> {
-//     let sessions = fs::read_dir(dir)?
-//         .filter_map(|e| {
-//             let path = e.ok()?.path();
-//             (path.extension()?.to_str()? == "desktop").then_some(path)
-//         })
-//         .filter_map(|path| {
-//             let file = File::open(path).expect("Unable to read file");
-//             let reader = io::BufReader::new(file);
-//
-//             let mut session = Session::default();
-//
-//             for line in reader.lines() {
-//                 let parts: Vec<&str> = line.unwrap().split("=").collect();
-//
-//                 if !session.name.is_empty() && !session.exec.is_empty() {
-//                     break;
-//                 }
-//
-//                 let key = parts.get(0).unwrap_or(&"").to_string();
-//                 let value = parts.get(1).unwrap_or(&"").to_string();
-//
-//                 if key.trim() == "Name" {
-//                     session.name = value.clone();
-//                 }
-//
-//                 if key.trim() == "Exec" {
-//                     session.exec = value.clone()
-//                 }
-//             }
-//
-//             if session.name.is_empty() || session.exec.is_empty() {
-//                 return None;
-//             }
-//
-//             Some(session)
-//         })
-//         .collect::<Vec<Session>>();
-//
-//     Ok(sessions)
-// }
-//
-pub fn read_sessions() -> Result<Vec<Session>> {
+/// Discovers the installed sessions. When several directories provide the same
+/// desktop-file ID for a session kind, the one found first wins, so a local
+/// entry (even a `Hidden=true` one) masks the vendor's. Commands from greetd's
+/// environments file are listed alongside them.
+pub fn read_sessions(config: &Config) -> Result<Vec<Session>> {
+    let locale = Locale::from_env();
+    let mut seen = HashSet::new();
     let mut sessions = Vec::new();
 
-    let mut search_paths: Vec<String> = vec![
-        "/usr/share/wayland-sessions".into(),
-        "/usr/share/xsessions".into(),
-        "/usr/local/share/wayland-sessions".into(),
-        "/usr/local/share/xsessions".into(),
-        "/etc/X11/Sessions".into(),
-    ];
-    if let Ok(home) = env::var("HOME") {
-        search_paths.push(format!("{home}/.local/share/wayland-sessions"));
-        search_paths.push(format!("{home}/.local/share/xsessions"));
+    for (dir, kind) in session_dirs(config) {
+        for (id, entry) in read_entries_in_dir(&dir) {
+            if !seen.insert((kind, id.clone())) {
+                continue;
+            }
+            sessions.extend(session_from_entry(id, kind, &entry, locale.as_ref()));
+        }
     }
 
-    for path in search_paths {
-        if let Ok(mut found) = read_sessions_in_dir(&path) {
-            sessions.append(&mut found);
+    if let Some(path) = &config.discovery.environments_file {
+        for session in read_environments(path) {
+            if seen.insert((session.kind, session.id.clone())) {
+                sessions.push(session);
+            }
         }
     }
 
+    sessions.sort_by(|a, b| {
+        a.name
+            .to_lowercase()
+            .cmp(&b.name.to_lowercase())
+            .then(a.kind.cmp(&b.kind))
+            .then_with(|| a.id.cmp(&b.id))
+    });
+
     Ok(sessions)
 }
+
 pub fn get_login_users() -> Result<Vec<String>> {
     let file = File::open("/etc/passwd")?;
     let reader = io::BufReader::new(file);
@@ -147,3 +389,300 @@ pub fn get_login_users() -> Result<Vec<String>> {
 
     Ok(users)
 }
+
+/// The login shell of `username`, field 7 of its `/etc/passwd` entry.
+pub fn login_shell(username: &str) -> Option<String> {
+    passwd_field(username, 6)
+}
+
+/// The uid and primary gid of `username`, fields 3 and 4 of its `/etc/passwd` entry.
+pub fn user_ids(username: &str) -> Option<(u32, u32)> {
+    Some((
+        passwd_field(username, 2)?.parse().ok()?,
+        passwd_field(username, 3)?.parse().ok()?,
+    ))
+}
+
+fn passwd_field(username: &str, idx: usize) -> Option<String> {
+    let file = File::open("/etc/passwd").ok()?;
+    let reader = io::BufReader::new(file);
+
+    reader.lines().map_while(Result::ok).find_map(|line| {
+        let fields: Vec<&str> = line.split(':').collect();
+        (fields.len() >= 7 && fields[0] == username && !fields[idx].is_empty())
+            .then(|| fields[idx].to_string())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(contents: &str) -> Config {
+        toml::from_str(contents).unwrap()
+    }
+
+    fn strings(items: &[&str]) -> Vec<String> {
+        items.iter().map(|item| item.to_string()).collect()
+    }
+
+    fn session(contents: &str) -> Option<Session> {
+        let entry = DesktopEntry::parse(contents);
+        session_from_entry(String::from("sway"), SessionKind::Wayland, &entry, None)
+    }
+
+    #[test]
+    fn session_env_per_kind() {
+        let config = config("");
+        let sway = Session {
+            id: String::from("sway"),
+            kind: SessionKind::Wayland,
+            desktop_names: strings(&["sway", "wlroots"]),
+            ..Session::default()
+        };
+        assert_eq!(
+            sway.env(&config),
+            [
+                "DESKTOP_SESSION=sway",
+                "XDG_CURRENT_DESKTOP=sway:wlroots",
+                "XDG_SESSION_DESKTOP=sway",
+                "XDG_SESSION_TYPE=wayland",
+            ]
+        );
+
+        let xfce = Session {
+            id: String::from("xfce"),
+            kind: SessionKind::X11,
+            ..Session::default()
+        };
+        assert_eq!(
+            xfce.env(&config),
+            [
+                "DESKTOP_SESSION=xfce",
+                "XDG_SESSION_DESKTOP=xfce",
+                "XDG_SESSION_TYPE=x11",
+            ]
+        );
+    }
+
+    #[test]
+    fn session_env_overrides_global_env() {
+        let config = config(
+            "[env]\n\
+             XDG_CURRENT_DESKTOP = \"global\"\n\
+             MOZ_ENABLE_WAYLAND = \"1\"\n\
+             [sessions.sway.env]\n\
+             XDG_CURRENT_DESKTOP = \"sway\"\n\
+             WLR_NO_HARDWARE_CURSORS = \"1\"\n",
+        );
+        let session = |id: &str| Session {
+            id: id.to_string(),
+            desktop_names: strings(&["wlroots"]),
+            ..Session::default()
+        };
+
+        assert_eq!(
+            session("sway").env(&config),
+            [
+                "DESKTOP_SESSION=sway",
+                "MOZ_ENABLE_WAYLAND=1",
+                "WLR_NO_HARDWARE_CURSORS=1",
+                "XDG_CURRENT_DESKTOP=sway",
+                "XDG_SESSION_DESKTOP=sway",
+                "XDG_SESSION_TYPE=wayland",
+            ]
+        );
+        assert_eq!(
+            session("river").env(&config),
+            [
+                "DESKTOP_SESSION=river",
+                "MOZ_ENABLE_WAYLAND=1",
+                "XDG_CURRENT_DESKTOP=global",
+                "XDG_SESSION_DESKTOP=river",
+                "XDG_SESSION_TYPE=wayland",
+            ]
+        );
+    }
+
+    #[test]
+    fn hidden_entries_are_not_sessions() {
+        let entry = "[Desktop Entry]\nName=Sway\nExec=sway\n";
+        assert_eq!(
+            session(entry).map(|s| s.exec),
+            Some(vec![String::from("sway")])
+        );
+        assert!(session(&format!("{entry}Hidden=true\n")).is_none());
+        assert!(session(&format!("{entry}NoDisplay=true\n")).is_none());
+        assert!(session(&format!("{entry}NoDisplay=false\n")).is_some());
+        assert!(session(&format!("{entry}Type=Link\n")).is_none());
+        assert!(session("[Desktop Entry]\nName=Sway\n").is_none());
+    }
+
+    #[test]
+    fn sessions_follow_xdg_precedence() {
+        let dir = tempfile::tempdir().unwrap();
+        let home = dir.path().join("home");
+        let vendor = dir.path().join("vendor");
+        // SAFETY: no other test reads or writes the XDG data directory variables.
+        unsafe {
+            env::set_var("XDG_DATA_HOME", &home);
+            env::set_var("XDG_DATA_DIRS", format!("{}:/usr/share", vendor.display()));
+        }
+
+        assert_eq!(
+            session_dirs(&config("")),
+            [
+                (home.join("wayland-sessions"), SessionKind::Wayland),
+                (home.join("xsessions"), SessionKind::X11),
+                (vendor.join("wayland-sessions"), SessionKind::Wayland),
+                (vendor.join("xsessions"), SessionKind::X11),
+                ("/usr/share/wayland-sessions".into(), SessionKind::Wayland),
+                ("/usr/share/xsessions".into(), SessionKind::X11),
+                (
+                    "/usr/local/share/wayland-sessions".into(),
+                    SessionKind::Wayland
+                ),
+                ("/usr/local/share/xsessions".into(), SessionKind::X11),
+                ("/etc/X11/Sessions".into(), SessionKind::X11),
+            ]
+        );
+
+        let entry = |name: &str| format!("[Desktop Entry]\nName={name}\nExec=true\n");
+        for (path, contents) in [
+            (
+                "home/wayland-sessions/ferrite-a.desktop",
+                entry("A") + "Hidden=true\n",
+            ),
+            ("home/wayland-sessions/ferrite-b.desktop", entry("Local B")),
+            (
+                "vendor/wayland-sessions/ferrite-a.desktop",
+                entry("Vendor A"),
+            ),
+            (
+                "vendor/wayland-sessions/ferrite-b.desktop",
+                entry("Vendor B"),
+            ),
+            ("vendor/wayland-sessions/ferrite-c.desktop", entry("C")),
+            ("vendor/xsessions/ferrite-a.desktop", entry("X11 A")),
+        ] {
+            let path = dir.path().join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, contents).unwrap();
+        }
+
+        let found: Vec<_> = read_sessions(&config(""))
+            .unwrap()
+            .into_iter()
+            .filter(|s| s.id.starts_with("ferrite-"))
+            .map(|s| (s.id, s.kind, s.name))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                (
+                    String::from("ferrite-c"),
+                    SessionKind::Wayland,
+                    String::from("C")
+                ),
+                (
+                    String::from("ferrite-b"),
+                    SessionKind::Wayland,
+                    String::from("Local B")
+                ),
+                (
+                    String::from("ferrite-a"),
+                    SessionKind::X11,
+                    String::from("X11 A")
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn read_environments_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("environments");
+        fs::write(
+            &path,
+            "# shells\nzsh\n\n  fish -l  \nsh -c \"exec bash -l\"\nbad \"quote\n",
+        )
+        .unwrap();
+
+        let sessions: Vec<_> = read_environments(&path)
+            .into_iter()
+            .map(|s| (s.id, s.exec, s.kind))
+            .collect();
+        assert_eq!(
+            sessions,
+            [
+                (String::from("zsh"), strings(&["zsh"]), SessionKind::Tty),
+                (
+                    String::from("fish -l"),
+                    strings(&["fish", "-l"]),
+                    SessionKind::Tty
+                ),
+                (
+                    String::from("sh -c \"exec bash -l\""),
+                    strings(&["sh", "-c", "exec bash -l"]),
+                    SessionKind::Tty
+                ),
+            ]
+        );
+        assert!(read_environments(&dir.path().join("missing")).is_empty());
+    }
+
+    #[test]
+    fn wrap_with_configured_wrappers() {
+        let config = config(
+            "[wrappers]\n\
+             wayland = [\"/bin/sh\", \"-l\", \"-c\"]\n\
+             x11 = [\"startx\", \"--\"]\n\
+             [sessions.plain]\n\
+             wrap = false\n",
+        );
+        let session = |id: &str, kind| Session {
+            id: id.to_string(),
+            kind,
+            ..Session::default()
+        };
+
+        assert_eq!(
+            session("sway", SessionKind::Wayland).wrap(
+                strings(&["sway", "--config", "/etc/my sway", "it's", "$HOME"]),
+                &config
+            ),
+            [
+                "/bin/sh",
+                "-l",
+                "-c",
+                r"exec 'sway' '--config' '/etc/my sway' 'it'\''s' '$HOME'",
+            ]
+        );
+        assert_eq!(
+            session("xfce", SessionKind::X11).wrap(strings(&["startxfce4"]), &config),
+            ["startx", "--", "startxfce4"]
+        );
+        assert_eq!(
+            session("plain", SessionKind::Wayland).wrap(strings(&["plain", "$HOME"]), &config),
+            ["plain", "$HOME"]
+        );
+        assert_eq!(
+            session("zsh", SessionKind::Tty).wrap(strings(&["zsh"]), &config),
+            ["zsh"]
+        );
+    }
+
+    #[test]
+    fn shell_join_survives_the_shell() {
+        let args = strings(&["printf", "<%s>", "it's", "a  b", "$HOME", "`id`", "\\", ""]);
+        let output = std::process::Command::new("sh")
+            .arg("-c")
+            .arg(shell_join(&args))
+            .output()
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(output.stdout).unwrap(),
+            "<it's><a  b><$HOME><`id`><\\><>"
+        );
+    }
+}
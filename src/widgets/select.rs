@@ -2,9 +2,11 @@ use ratatui::{
     Frame,
     crossterm::event::{Event, KeyCode},
     layout::{Constraint, Layout, Rect},
+    style::Stylize,
     widgets::{Paragraph, Widget},
 };
 
+use crate::theme::Theme;
 use crate::widgets::widget::InputField;
 
 #[derive(Debug, Default)]
@@ -16,7 +18,7 @@ pub struct SelectField<T, F> {
     pub transform: F,
 }
 
-impl<T, F> InputField<T> for SelectField<T, F>
+impl<T, F> InputField<Option<T>> for SelectField<T, F>
 where
     T: Clone,
     F: Fn(&T) -> String,
@@ -30,19 +32,17 @@ where
                 KeyCode::Left => {
                     self.selected_idx = self.selected_idx.saturating_sub(1);
                 }
-                KeyCode::Right => {
-                    if self.items.len() > (self.selected_idx + 1) {
-                        self.selected_idx += 1;
-                    }
+                KeyCode::Right if self.items.len() > (self.selected_idx + 1) => {
+                    self.selected_idx += 1;
                 }
                 _ => {}
             }
         };
     }
 
-    fn render(&self, frame: &mut Frame, focus_index: &u8, area: Rect) {
+    fn render(&self, frame: &mut Frame, focus_index: &u8, area: Rect, theme: &Theme) {
         let is_focused = self.index == *focus_index;
-        let style = self.base_style(is_focused);
+        let style = self.base_style(is_focused, theme);
         let [arrow_left, value, arrow_right] = Layout::horizontal([
             Constraint::Length(2),
             Constraint::Fill(1),
@@ -51,7 +51,9 @@ where
         .margin(1)
         .areas(area);
 
-        Paragraph::new("<").render(arrow_left, frame.buffer_mut());
+        Paragraph::new("<")
+            .style(style)
+            .render(arrow_left, frame.buffer_mut());
 
         let text = self
             .items
@@ -59,17 +61,22 @@ where
             .map(|item| (self.transform)(item))
             .unwrap_or_else(|| String::from(""));
 
-        Paragraph::new(text).render(value, frame.buffer_mut());
-
-        Paragraph::new(">").render(arrow_right, frame.buffer_mut());
+        Paragraph::new(text)
+            .fg(theme.foreground)
+            .render(value, frame.buffer_mut());
 
-        self.base_block()
+        Paragraph::new(">")
             .style(style)
+            .render(arrow_right, frame.buffer_mut());
+
+        self.base_block(theme)
+            .border_style(style)
+            .title_style(style)
             .title(self.label.clone())
             .render(area, frame.buffer_mut());
     }
 
-    fn get_value(&self) -> T {
-        self.items.get(self.selected_idx).unwrap().clone()
+    fn get_value(&self) -> Option<T> {
+        self.items.get(self.selected_idx).cloned()
     }
 }
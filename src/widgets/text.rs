@@ -1,7 +1,8 @@
-use ratatui::{Frame, crossterm::event::Event, layout::Rect, widgets::Paragraph};
+use ratatui::{Frame, crossterm::event::Event, layout::Rect, style::Style, widgets::Paragraph};
 use tui_input::Input;
 use tui_input::backend::crossterm::EventHandler;
 
+use crate::theme::Theme;
 use crate::widgets::widget::InputField;
 
 #[derive(Debug, Default)]
@@ -19,21 +20,30 @@ impl InputField<String> for TextField {
         }
     }
 
-    fn render(&self, frame: &mut Frame, focus_index: &u8, area: Rect) {
+    fn render(&self, frame: &mut Frame, focus_index: &u8, area: Rect, theme: &Theme) {
         let width = area.width.max(3) - 3;
         let scroll = self.input.visual_scroll(width as usize);
         let is_focused = self.index == *focus_index;
-        let style = self.base_style(is_focused);
+        let style = self.base_style(is_focused, theme);
 
         let value: String = match self.mask.as_deref() {
             Some(mask_chr) => mask_chr.repeat(self.input.value().chars().count()),
             None => self.input.value().to_string(),
         };
 
+        let text_color = match self.mask {
+            Some(_) => theme.mask,
+            None => theme.foreground,
+        };
         let input = Paragraph::new(value)
-            .style(style)
+            .style(Style::default().fg(text_color))
             .scroll((0, scroll as u16))
-            .block(self.base_block().title(self.label.clone()));
+            .block(
+                self.base_block(theme)
+                    .border_style(style)
+                    .title_style(style)
+                    .title(self.label.clone()),
+            );
 
         frame.render_widget(input, area);
 
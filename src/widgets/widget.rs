@@ -1,24 +1,22 @@
-use ratatui::{
-    Frame,
-    crossterm::event::Event,
-    layout::Rect,
-    style::{Color, Style},
-    widgets::{Block, BorderType},
-};
+use ratatui::{Frame, crossterm::event::Event, layout::Rect, style::Style, widgets::Block};
+
+use crate::theme::Theme;
 
 pub trait InputField<T> {
     fn handle_event(&mut self, focus_index: u8, event: &Event);
-    fn render(&self, frame: &mut Frame, focus_index: &u8, area: Rect);
+    fn render(&self, frame: &mut Frame, focus_index: &u8, area: Rect, theme: &Theme);
     fn get_value(&self) -> T;
 
-    fn base_style(&self, is_focused: bool) -> Style {
+    fn base_style(&self, is_focused: bool, theme: &Theme) -> Style {
         if is_focused {
-            return Style::default().fg(Color::Yellow);
+            return Style::default()
+                .fg(theme.focused_border)
+                .add_modifier(theme.focus_modifier);
         }
-        Style::default()
+        Style::default().fg(theme.border)
     }
 
-    fn base_block<'a>(&self) -> Block<'a> {
-        Block::bordered().border_type(BorderType::Plain)
+    fn base_block<'a>(&self, theme: &Theme) -> Block<'a> {
+        Block::bordered().border_set(theme.border_set(theme.field_border))
     }
 }
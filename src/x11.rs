@@ -0,0 +1,313 @@
+use anyhow::{Context, bail};
+use std::{
+    env,
+    ffi::{CString, OsString},
+    fs::{self, File},
+    io::{self, Read, Write},
+    os::{
+        fd::{AsRawFd, FromRawFd},
+        unix::{ffi::OsStringExt, process::CommandExt},
+    },
+    path::{Path, PathBuf},
+    process::{Child, Command},
+    time::Duration,
+};
+
+use crate::config::X11Config;
+
+/// Argument that makes the ferrite binary run [`run_session`].
+pub const SESSION_ARG: &str = "x11-session";
+
+const COOKIE_NAME: &str = "MIT-MAGIC-COOKIE-1";
+/// Xauthority family matching any address; with an empty display number the
+/// entry also matches any display.
+const FAMILY_WILD: u16 = 0xffff;
+const SERVER_TIMEOUT: Duration = Duration::from_secs(10);
+
+pub fn generate_cookie() -> io::Result<[u8; 16]> {
+    let mut cookie = [0; 16];
+    File::open("/dev/urandom")?.read_exact(&mut cookie)?;
+    Ok(cookie)
+}
+
+/// An Xauthority entry granting `cookie` access to any local display, so it
+/// can be written before the server has picked its display.
+pub fn xauthority_entry(cookie: &[u8]) -> Vec<u8> {
+    let mut entry = FAMILY_WILD.to_be_bytes().to_vec();
+    for field in [&[][..], &[][..], COOKIE_NAME.as_bytes(), cookie] {
+        entry.extend((field.len() as u16).to_be_bytes());
+        entry.extend(field);
+    }
+    entry
+}
+
+/// Creates a new Xauthority file in `dir`, readable only by the user, so that
+/// the user's own `~/.Xauthority` is left alone.
+fn create_xauthority(dir: &Path, cookie: &[u8]) -> io::Result<PathBuf> {
+    let template = CString::new(dir.join("ferrite-xauth-XXXXXX").into_os_string().into_vec())?;
+    let mut template = template.into_bytes_with_nul();
+    // SAFETY: `template` is a NUL-terminated buffer that mkstemp(3) fills in.
+    let fd = unsafe { libc::mkstemp(template.as_mut_ptr().cast()) };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    template.pop();
+    let path = PathBuf::from(OsString::from_vec(template));
+
+    // SAFETY: mkstemp(3) returned a new descriptor that nothing else owns.
+    let mut file = unsafe { File::from_raw_fd(fd) };
+    if let Err(e) = file.write_all(&xauthority_entry(cookie)) {
+        let _ = fs::remove_file(&path);
+        return Err(e);
+    }
+    Ok(path)
+}
+
+/// The X server command line: the configured server followed by the VT, auth
+/// file and `-displayfd` arguments, so that the server picks a free display
+/// itself and writes its number to `displayfd`.
+pub fn server_command(
+    config: &X11Config,
+    vt: Option<&str>,
+    auth: &Path,
+    displayfd: i32,
+) -> Vec<String> {
+    let mut cmd = config.server.clone();
+    cmd.extend(vt.map(|vt| format!("vt{vt}")));
+    cmd.push(String::from("-auth"));
+    cmd.push(auth.to_string_lossy().into_owned());
+    cmd.push(String::from("-displayfd"));
+    cmd.push(displayfd.to_string());
+    cmd
+}
+
+/// Prepares the greetd command for an X11 session started by ferrite's own
+/// launcher: `ferrite x11-session <client...>`. The display is chosen inside
+/// the session, when the server starts.
+pub fn native_session(client: Vec<String>) -> anyhow::Result<Vec<String>> {
+    let exe = env::current_exe().context("locating the ferrite binary")?;
+
+    let mut cmd = vec![exe.to_string_lossy().into_owned(), SESSION_ARG.to_string()];
+    cmd.extend(client);
+    Ok(cmd)
+}
+
+/// Runs inside the user's session: writes a cookie to a new Xauthority file,
+/// starts the X server on a free display, runs the client with `DISPLAY` and
+/// `XAUTHORITY` set and stops the server once the client exits. Returns the
+/// client's exit code.
+pub fn run_session(client: &[String], config: &X11Config) -> anyhow::Result<i32> {
+    let Some((program, args)) = client.split_first() else {
+        bail!("no X11 client command given");
+    };
+
+    let runtime_dir = env::var_os("XDG_RUNTIME_DIR")
+        .filter(|dir| !dir.is_empty())
+        .map_or_else(env::temp_dir, PathBuf::from);
+    let auth = create_xauthority(&runtime_dir, &generate_cookie()?)
+        .with_context(|| format!("writing an Xauthority file in {}", runtime_dir.display()))?;
+
+    let vt = env::var("XDG_VTNR").ok();
+    let result = start_server(config, vt.as_deref(), &auth).and_then(|(mut server, display)| {
+        let status = Command::new(program)
+            .args(args)
+            .env("DISPLAY", format!(":{display}"))
+            .env("XAUTHORITY", &auth)
+            .status();
+        stop_server(&mut server);
+        Ok(status
+            .with_context(|| format!("starting {program}"))?
+            .code()
+            .unwrap_or(1))
+    });
+    let _ = fs::remove_file(&auth);
+    result
+}
+
+/// Starts the X server and waits until it reports the display it listens on.
+pub fn start_server(
+    config: &X11Config,
+    vt: Option<&str>,
+    auth: &Path,
+) -> anyhow::Result<(Child, u32)> {
+    let (mut reader, writer) = io::pipe()?;
+    let displayfd = writer.as_raw_fd();
+
+    let server_cmd = server_command(config, vt, auth, displayfd);
+    let Some((server_program, server_args)) = server_cmd.split_first() else {
+        bail!("no X server command configured");
+    };
+    let mut command = Command::new(server_program);
+    command.args(server_args);
+    // SAFETY: only fcntl(2) runs between fork and exec, to let the server
+    // inherit the pipe.
+    unsafe {
+        command.pre_exec(move || {
+            if libc::fcntl(displayfd, libc::F_SETFD, 0) == -1 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(())
+        });
+    }
+    let mut server = command
+        .spawn()
+        .with_context(|| format!("starting {server_program}"))?;
+    drop(writer);
+
+    match read_display(&mut reader) {
+        Ok(display) => Ok((server, display)),
+        Err(e) => {
+            stop_server(&mut server);
+            Err(e)
+        }
+    }
+}
+
+/// Reads the display number the server writes once it accepts connections.
+fn read_display(reader: &mut io::PipeReader) -> anyhow::Result<u32> {
+    let mut pollfd = libc::pollfd {
+        fd: reader.as_raw_fd(),
+        events: libc::POLLIN,
+        revents: 0,
+    };
+    let mut output = Vec::new();
+    while !output.ends_with(b"\n") {
+        // SAFETY: poll(2) on one valid descriptor.
+        let ready = unsafe { libc::poll(&mut pollfd, 1, SERVER_TIMEOUT.as_millis() as i32) };
+        if ready == 0 {
+            bail!("X server did not start within {SERVER_TIMEOUT:?}");
+        }
+        let mut buf = [0; 16];
+        match reader.read(&mut buf)? {
+            0 => bail!("X server exited early"),
+            n => output.extend(&buf[..n]),
+        }
+    }
+
+    let display = String::from_utf8_lossy(&output);
+    display
+        .trim()
+        .parse()
+        .with_context(|| format!("X server reported an invalid display {display:?}"))
+}
+
+/// Asks the server to shut down with SIGTERM so it can restore the console.
+fn stop_server(server: &mut Child) {
+    // SAFETY: plain kill(2) on the server's own pid.
+    unsafe {
+        libc::kill(server.id() as libc::pid_t, libc::SIGTERM);
+    }
+    let _ = server.wait();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::desktop::try_exec;
+
+    #[test]
+    fn xauthority_entry_layout() {
+        let cookie = [0xab; 16];
+        let entry = xauthority_entry(&cookie);
+
+        let mut expected = vec![0xff, 0xff, 0, 0, 0, 0, 0, 18];
+        expected.extend(b"MIT-MAGIC-COOKIE-1");
+        expected.extend([0, 16]);
+        expected.extend(cookie);
+        assert_eq!(entry, expected);
+    }
+
+    #[test]
+    fn xauthority_file_is_private_and_new() {
+        let dir = env::temp_dir();
+        let first = create_xauthority(&dir, &[1; 16]).unwrap();
+        let second = create_xauthority(&dir, &[2; 16]).unwrap();
+        assert_ne!(first, second);
+
+        use std::os::unix::fs::PermissionsExt;
+        let meta = fs::metadata(&first).unwrap();
+        assert_eq!(meta.permissions().mode() & 0o777, 0o600);
+        assert_eq!(fs::read(&first).unwrap(), xauthority_entry(&[1; 16]));
+
+        fs::remove_file(first).unwrap();
+        fs::remove_file(second).unwrap();
+    }
+
+    #[test]
+    fn server_command_arguments() {
+        let config = X11Config {
+            native: true,
+            server: vec![
+                String::from("Xorg"),
+                String::from("-nolisten"),
+                String::from("tcp"),
+            ],
+        };
+        let cmd = server_command(&config, Some("7"), Path::new("/run/user/1000/xauth"), 5);
+        assert_eq!(
+            cmd,
+            [
+                "Xorg",
+                "-nolisten",
+                "tcp",
+                "vt7",
+                "-auth",
+                "/run/user/1000/xauth",
+                "-displayfd",
+                "5"
+            ]
+        );
+        assert!(
+            !server_command(&config, None, Path::new("a"), 5)
+                .iter()
+                .any(|arg| arg.starts_with("vt"))
+        );
+    }
+
+    #[test]
+    fn start_server_reads_displayfd() {
+        // Stands in for a server: writes a display number to the -displayfd
+        // descriptor, then waits to be stopped.
+        let script = r#"for arg; do [ "$prev" = -displayfd ] && echo 42 >&"$arg"; prev=$arg; done; exec sleep 10"#;
+        let config = X11Config {
+            native: true,
+            server: vec![
+                String::from("sh"),
+                String::from("-c"),
+                script.to_string(),
+                String::from("server"),
+            ],
+        };
+        let (mut server, display) = start_server(&config, None, Path::new("auth")).unwrap();
+        assert_eq!(display, 42);
+        stop_server(&mut server);
+    }
+
+    #[test]
+    fn start_server_fails_when_server_exits() {
+        let config = X11Config {
+            native: true,
+            server: vec![String::from("false")],
+        };
+        assert!(start_server(&config, None, Path::new("auth")).is_err());
+    }
+
+    /// Starts a real server when Xvfb is installed; skipped otherwise.
+    #[test]
+    fn xvfb_reports_its_display() {
+        if !try_exec("Xvfb") {
+            eprintln!("Xvfb is not installed, skipping");
+            return;
+        }
+        let config = X11Config {
+            native: true,
+            server: vec![String::from("Xvfb")],
+        };
+        let auth = create_xauthority(&env::temp_dir(), &generate_cookie().unwrap()).unwrap();
+
+        let (mut server, display) = start_server(&config, None, &auth).unwrap();
+        assert!(Path::new(&format!("/tmp/.X11-unix/X{display}")).exists());
+        stop_server(&mut server);
+        fs::remove_file(auth).unwrap();
+    }
+}